@@ -4,6 +4,7 @@
 network=${network:-testnet}
 accountId=${accountId:-req0.mennat0.testnet}
 oracle=${oracle:-fpo.mennat0.testnet}
+backupOracle=${backupOracle:-}
 master=${master:-mennat0.testnet}
 initialBalance=${initialBalance:-5}
 
@@ -26,4 +27,8 @@ NEAR_ENV=$network near create-account $accountId --masterAccount $master --initi
 NEAR_ENV=$network near deploy --accountId $accountId --wasmFile ./res/consumer.wasm 
 
 # initialize the contract
-near call $accountId new "{\"oracle\": \"$oracle\"}" --accountId $accountId
\ No newline at end of file
+if [ -n "$backupOracle" ]; then
+    near call $accountId new "{\"oracle\": \"$oracle\", \"backup_oracle\": \"$backupOracle\"}" --accountId $accountId
+else
+    near call $accountId new "{\"oracle\": \"$oracle\"}" --accountId $accountId
+fi
\ No newline at end of file
@@ -1,13 +1,18 @@
+#![allow(clippy::too_many_arguments)]
+
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
 use near_sdk::collections::LookupMap;
 use near_sdk::json_types::U128;
 use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::Balance;
 use near_sdk::Timestamp;
-use near_sdk::{env, ext_contract, log, near_bindgen, AccountId, Gas, PanicOnDefault, Promise};
+use near_sdk::{
+    env, ext_contract, log, near_bindgen, AccountId, Gas, PanicOnDefault, Promise, PromiseOrValue,
+};
 
 const NO_DEPOSIT: Balance = 0;
 const GAS_FOR_RESOLVE_TRANSFER: Gas = Gas(5_000_000_000_000);
+const GAS_FOR_FALLBACK: Gas = Gas(20_000_000_000_000); // Lets the callback query the backup oracle
 
 #[ext_contract(fpo)]
 trait FPO {
@@ -29,7 +34,8 @@ trait FPO {
 
 #[ext_contract(ext_self)]
 trait RequestResolver {
-    fn price_callback(&self) -> Option<U128>;
+    fn price_callback(&mut self, query: PriceQuery, oracle: AccountId)
+        -> (Option<U128>, AccountId);
     fn prices_callback(&self) -> Vec<Option<U128>>;
 }
 
@@ -40,6 +46,33 @@ pub struct PriceEntry {
     price_type: PriceType,
 }
 
+/// A price entry forwarded by the FPO's `get_entries_call()`, its other fields are ignored
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ForwardedEntry {
+    pub price: U128,
+    pub decimals: u16,
+    pub last_update: Timestamp,
+}
+
+/// A query for a single price, retried on the backup oracle if the primary has no answer
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum PriceQuery {
+    Price {
+        pair: String,
+        provider: AccountId,
+    },
+    Mean {
+        pairs: Vec<String>,
+        providers: Vec<AccountId>,
+        min_last_update: Timestamp,
+    },
+    Median {
+        pairs: Vec<String>,
+        providers: Vec<AccountId>,
+        min_last_update: Timestamp,
+    },
+}
+
 #[derive(BorshDeserialize, BorshSerialize)]
 
 pub struct Provider {
@@ -66,8 +99,11 @@ impl Default for Provider {
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
 pub struct Consumer {
-    oracle: AccountId,
+    oracle: AccountId,                         // primary FPO deployment
+    backup_oracle: Option<AccountId>,          // queried if the primary has no answer
     providers: LookupMap<AccountId, Provider>, // maps:  AccountId => Provider
+    request_ids: LookupMap<String, u64>, // maps:  "{oracle}:{sender_id}" => id of the last request received
+    last_oracle: Option<AccountId>, // oracle that served the last price received by `price_callback()`
 }
 
 #[derive(
@@ -79,27 +115,69 @@ pub enum PriceType {
     Mean,
     Median,
     Collect, // same as multiple but with min_last_update
+    Derived, // computed by the FPO from other pairs
+    Entries, // full price entries instead of prices
 }
 
 #[near_bindgen]
 impl Consumer {
     #[init]
-    pub fn new(oracle: AccountId) -> Self {
+    pub fn new(oracle: AccountId, backup_oracle: Option<AccountId>) -> Self {
         Self {
             oracle,
+            backup_oracle,
             providers: LookupMap::new("p".as_bytes()),
+            request_ids: LookupMap::new("r".as_bytes()),
+            last_oracle: None,
         }
     }
 
+    /// @dev Panics unless the caller is the oracle or the backup oracle.
+    fn assert_oracle(&self) {
+        let caller = env::predecessor_account_id();
+        assert!(
+            caller == self.oracle || self.backup_oracle.as_ref() == Some(&caller),
+            "only the oracle can forward prices"
+        );
+    }
+
+    /// @dev Records the id of a request forwarded by the calling oracle, panics if it was received
+    /// already or arrives out of order. Every oracle counts its own request ids.
+    fn assert_new_request(&mut self, sender_id: &AccountId, request_id: u64) {
+        let key = format!("{}:{}", env::predecessor_account_id(), sender_id);
+        let last_request_id = self.request_ids.get(&key).unwrap_or(0);
+        assert!(
+            request_id > last_request_id,
+            "duplicate or out-of-order request id"
+        );
+        self.request_ids.insert(&key, &request_id);
+    }
+
+    /// @dev Gets the id of the last request `oracle` forwarded on behalf of `sender_id`.
+    pub fn get_last_request_id(&self, oracle: AccountId, sender_id: AccountId) -> u64 {
+        self.request_ids
+            .get(&format!("{}:{}", oracle, sender_id))
+            .unwrap_or(0)
+    }
+
     /// @dev Called by FPO contract after a `call()` call to forward a price to the consumer.
+    /// `msg` is the payload the caller passed to the `call()`, if any, and `request_id` must
+    /// increase with every forward the oracle makes for `sender_id`.
     pub fn on_price_received(
         &mut self,
         sender_id: AccountId,
+        request_id: u64,
         pairs: Vec<String>,
         providers: Vec<AccountId>,
         price_type: PriceType,
         results: Vec<Option<U128>>,
+        msg: Option<String>,
     ) {
+        self.assert_oracle();
+        self.assert_new_request(&sender_id, request_id);
+        if let Some(msg) = msg {
+            log!("Received prices for {}", msg);
+        }
         for index in 0..providers.len() {
             let provider_account_id = &providers[index];
             let mut provider = self
@@ -138,6 +216,51 @@ impl Consumer {
         }
     }
 
+    /// @dev Called by FPO contract after a `get_entries_call()` to forward price entries to the
+    /// consumer, caching the price of every entry that was found.
+    pub fn on_entries_received(
+        &mut self,
+        sender_id: AccountId,
+        request_id: u64,
+        pairs: Vec<String>,
+        providers: Vec<AccountId>,
+        price_type: PriceType,
+        results: Vec<Option<ForwardedEntry>>,
+        msg: Option<String>,
+    ) {
+        self.assert_oracle();
+        self.assert_new_request(&sender_id, request_id);
+        if let Some(msg) = msg {
+            log!("Received entries for {}", msg);
+        }
+        for (index, provider_account_id) in providers.iter().enumerate() {
+            let mut provider = self
+                .providers
+                .get(provider_account_id)
+                .unwrap_or_else(Provider::new);
+            let pair_name = format!("{}:{}", pairs[index], provider_account_id);
+
+            match &results[index] {
+                Some(result) => {
+                    let entry = PriceEntry {
+                        price: result.price,
+                        sender: sender_id.clone(),
+                        price_type,
+                    };
+                    provider.set_pair(pair_name, &entry);
+                }
+                None => log!("Not found"),
+            }
+
+            self.providers.insert(provider_account_id, &provider);
+        }
+    }
+
+    /// @dev Gets the oracle that served the last price fetched with `get_price()` or an aggregate.
+    pub fn get_last_oracle(&self) -> Option<AccountId> {
+        self.last_oracle.clone()
+    }
+
     /// @dev Gets a cached price from this contract.
     pub fn get_pair(&self, provider: AccountId, pair: String) -> PriceEntry {
         let pair_name = format!("{}:{}", pair, provider);
@@ -151,18 +274,7 @@ impl Consumer {
 
     /// @dev Fetches a price from the FPO with the answer forwarded to `price_callback()`.
     pub fn get_price(&self, pair: String, provider: AccountId) -> Promise {
-        fpo::get_price(
-            pair,
-            provider,
-            self.oracle.clone(),
-            NO_DEPOSIT,
-            GAS_FOR_RESOLVE_TRANSFER,
-        )
-        .then(ext_self::price_callback(
-            env::current_account_id(),
-            0,                      // yocto NEAR to attach to the callback
-            Gas(5_000_000_000_000), // gas to attach to the callback
-        ))
+        self.query_price(self.oracle.clone(), PriceQuery::Price { pair, provider })
     }
 
     /// @dev Fetches prices from the FPO with the answer forwarded to `prices_callback()`.
@@ -188,19 +300,14 @@ impl Consumer {
         providers: Vec<AccountId>,
         min_last_update: Timestamp,
     ) -> Promise {
-        fpo::aggregate_avg(
-            pairs,
-            providers,
-            min_last_update,
+        self.query_price(
             self.oracle.clone(),
-            NO_DEPOSIT,
-            GAS_FOR_RESOLVE_TRANSFER,
+            PriceQuery::Mean {
+                pairs,
+                providers,
+                min_last_update,
+            },
         )
-        .then(ext_self::price_callback(
-            env::current_account_id(),
-            0,                      // yocto NEAR to attach to the callback
-            Gas(5_000_000_000_000), // gas to attach to the callback
-        ))
     }
 
     /// @dev Fetches a median price from the FPO with the answer forwarded to `price_callback()`.
@@ -210,30 +317,85 @@ impl Consumer {
         providers: Vec<AccountId>,
         min_last_update: Timestamp,
     ) -> Promise {
-        fpo::aggregate_median(
-            pairs,
-            providers,
-            min_last_update,
+        self.query_price(
             self.oracle.clone(),
-            NO_DEPOSIT,
-            GAS_FOR_RESOLVE_TRANSFER,
+            PriceQuery::Median {
+                pairs,
+                providers,
+                min_last_update,
+            },
         )
-        .then(ext_self::price_callback(
+    }
+
+    /// @dev Sends a price query to an oracle with the answer forwarded to `price_callback()`.
+    fn query_price(&self, oracle: AccountId, query: PriceQuery) -> Promise {
+        let promise = match query.clone() {
+            PriceQuery::Price { pair, provider } => fpo::get_price(
+                pair,
+                provider,
+                oracle.clone(),
+                NO_DEPOSIT,
+                GAS_FOR_RESOLVE_TRANSFER,
+            ),
+            PriceQuery::Mean {
+                pairs,
+                providers,
+                min_last_update,
+            } => fpo::aggregate_avg(
+                pairs,
+                providers,
+                min_last_update,
+                oracle.clone(),
+                NO_DEPOSIT,
+                GAS_FOR_RESOLVE_TRANSFER,
+            ),
+            PriceQuery::Median {
+                pairs,
+                providers,
+                min_last_update,
+            } => fpo::aggregate_median(
+                pairs,
+                providers,
+                min_last_update,
+                oracle.clone(),
+                NO_DEPOSIT,
+                GAS_FOR_RESOLVE_TRANSFER,
+            ),
+        };
+        let callback_gas = if self.backup_oracle.is_some() && oracle == self.oracle {
+            GAS_FOR_FALLBACK
+        } else {
+            Gas(5_000_000_000_000)
+        };
+        promise.then(ext_self::price_callback(
+            query,
+            oracle,
             env::current_account_id(),
-            0,                      // yocto NEAR to attach to the callback
-            Gas(5_000_000_000_000), // gas to attach to the callback
+            0,            // yocto NEAR to attach to the callback
+            callback_gas, // gas to attach to the callback
         ))
     }
 
-    /// @dev Handles the callback from the FPO after a price is received.
+    /// @dev Handles the callback from the FPO after a price is received, asking the backup
+    /// oracle if the primary returned no price or failed. Records the oracle that served the
+    /// price and returns it with the price, or the last oracle asked if none had a price.
     #[private]
     pub fn price_callback(
-        #[callback_result] result: Result<U128, near_sdk::PromiseError>,
-    ) -> Option<U128> {
-        if let Ok(res) = result.as_ref() {
-            Some(*res)
-        } else {
-            None
+        &mut self,
+        query: PriceQuery,
+        oracle: AccountId,
+        #[callback_result] result: Result<Option<U128>, near_sdk::PromiseError>,
+    ) -> PromiseOrValue<(Option<U128>, AccountId)> {
+        if let Ok(Some(price)) = result {
+            self.last_oracle = Some(oracle.clone());
+            return PromiseOrValue::Value((Some(price), oracle));
+        }
+        match &self.backup_oracle {
+            Some(backup_oracle) if oracle == self.oracle => {
+                log!("No price from {}, asking {}", oracle, backup_oracle);
+                PromiseOrValue::Promise(self.query_price(backup_oracle.clone(), query))
+            }
+            _ => PromiseOrValue::Value((None, oracle)),
         }
     }
 
@@ -11,7 +11,7 @@ near_sdk_sim::lazy_static_include::lazy_static_include_bytes! {
 }
 
 pub const DEFAULT_GAS: u64 = 300_000_000_000_000;
-pub const STORAGE_COST: u128 = 1_700_000_000_000_000_000_000;
+pub const STORAGE_COST: u128 = 25_060_000_000_000_000_000_000;
 
 fn init() -> (
     UserAccount,
@@ -49,7 +49,7 @@ fn simulate_get_price() {
     provider1.call(
         fpo.account_id(),
         "create_pair",
-        &json!(["ETH/USD".to_string(), 8, U128(2000)])
+        &json!(["ETH/USD".to_string(), 8, U128(2000), null, null])
             .to_string()
             .into_bytes(),
         DEFAULT_GAS,
@@ -70,7 +70,7 @@ fn simulate_get_price() {
         &"2000".to_string()
     );
 
-    call!(provider2, consumer.new(fpo.account_id())).assert_success();
+    call!(provider2, consumer.new(fpo.account_id(), None)).assert_success();
 
     let outcome = call!(
         provider2,
@@ -84,6 +84,49 @@ fn simulate_get_price() {
     }
 }
 
+#[test]
+fn simulate_get_price_from_backup() {
+    let (root, fpo, consumer) = init();
+    let backup: ContractAccount<FPOContractContract> = deploy! {
+        contract: FPOContractContract,
+        contract_id: "backupfpo".to_string(),
+        bytes: &FPO_BYTES,
+        signer_account: root
+    };
+
+    let provider1 = root.create_user("provider1".parse().unwrap(), to_yocto("1000000"));
+    let user = root.create_user("user".parse().unwrap(), to_yocto("1000000"));
+
+    call!(root, fpo.new()).assert_success();
+    call!(root, backup.new()).assert_success();
+    call!(
+        root,
+        consumer.new(fpo.account_id(), Some(backup.account_id()))
+    )
+    .assert_success();
+
+    // only the backup knows the pair
+    provider1.call(
+        backup.account_id(),
+        "create_pair",
+        &json!(["ETH/USD".to_string(), 8, U128(2000), null, null])
+            .to_string()
+            .into_bytes(),
+        DEFAULT_GAS,
+        STORAGE_COST, // attached deposit
+    );
+
+    let outcome = call!(
+        user,
+        consumer.get_price("ETH/USD".to_string(), provider1.account_id())
+    );
+    outcome.assert_success();
+    debug_assert_eq!(outcome.unwrap_json_value(), json!(["2000", "backupfpo"]));
+
+    let last_oracle = call!(user, consumer.get_last_oracle());
+    debug_assert_eq!(last_oracle.unwrap_json_value(), json!("backupfpo"));
+}
+
 #[test]
 fn simulate_get_prices() {
     let (root, fpo, consumer) = init();
@@ -92,13 +135,13 @@ fn simulate_get_prices() {
     let provider2 = root.create_user("provider2".parse().unwrap(), to_yocto("1000000"));
 
     call!(root, fpo.new()).assert_success();
-    call!(root, consumer.new(fpo.account_id())).assert_success();
+    call!(root, consumer.new(fpo.account_id(), None)).assert_success();
 
     // create a price pair, check if it exists, and get the value
     provider1.call(
         fpo.account_id(),
         "create_pair",
-        &json!(["ETH/USD".to_string(), 8, U128(2000)])
+        &json!(["ETH/USD".to_string(), 8, U128(2000), null, null])
             .to_string()
             .into_bytes(),
         DEFAULT_GAS,
@@ -122,7 +165,7 @@ fn simulate_get_prices() {
     provider2.call(
         fpo.account_id(),
         "create_pair",
-        &json!(["ETH/USD".to_string(), 8, U128(4000)])
+        &json!(["ETH/USD".to_string(), 8, U128(4000), null, null])
             .to_string()
             .into_bytes(),
         DEFAULT_GAS,
@@ -165,13 +208,13 @@ fn simulate_agg_avg() {
     let provider2 = root.create_user("provider2".parse().unwrap(), to_yocto("1000000"));
 
     call!(root, fpo.new()).assert_success();
-    call!(root, consumer.new(fpo.account_id())).assert_success();
+    call!(root, consumer.new(fpo.account_id(), None)).assert_success();
 
     // create a price pair, check if it exists, and get the value
     provider1.call(
         fpo.account_id(),
         "create_pair",
-        &json!(["ETH/USD".to_string(), 8, U128(2000)])
+        &json!(["ETH/USD".to_string(), 8, U128(2000), null, null])
             .to_string()
             .into_bytes(),
         DEFAULT_GAS,
@@ -195,7 +238,7 @@ fn simulate_agg_avg() {
     provider2.call(
         fpo.account_id(),
         "create_pair",
-        &json!(["ETH/USD".to_string(), 8, U128(4000)])
+        &json!(["ETH/USD".to_string(), 8, U128(4000), null, null])
             .to_string()
             .into_bytes(),
         DEFAULT_GAS,
@@ -239,13 +282,13 @@ fn simulate_agg_median() {
     let provider2 = root.create_user("provider2".parse().unwrap(), to_yocto("1000000"));
 
     call!(root, fpo.new()).assert_success();
-    call!(root, consumer.new(fpo.account_id())).assert_success();
+    call!(root, consumer.new(fpo.account_id(), None)).assert_success();
 
     // create a price pair, check if it exists, and get the value
     provider1.call(
         fpo.account_id(),
         "create_pair",
-        &json!(["ETH/USD".to_string(), 8, U128(2000)])
+        &json!(["ETH/USD".to_string(), 8, U128(2000), null, null])
             .to_string()
             .into_bytes(),
         DEFAULT_GAS,
@@ -269,7 +312,7 @@ fn simulate_agg_median() {
     provider2.call(
         fpo.account_id(),
         "create_pair",
-        &json!(["ETH/USD".to_string(), 8, U128(4000)])
+        &json!(["ETH/USD".to_string(), 8, U128(4000), null, null])
             .to_string()
             .into_bytes(),
         DEFAULT_GAS,
@@ -313,13 +356,13 @@ fn simulate_get_price_call() {
     let user = root.create_user("user".parse().unwrap(), to_yocto("1000000"));
 
     call!(root, fpo.new()).assert_success();
-    call!(root, consumer.new(fpo.account_id())).assert_success();
+    call!(root, consumer.new(fpo.account_id(), None)).assert_success();
 
     // create a price pair, check if it exists, and get the value
     provider1.call(
         fpo.account_id(),
         "create_pair",
-        &json!(["ETH/USD".to_string(), 8, U128(2000)])
+        &json!(["ETH/USD".to_string(), 8, U128(2000), null, null])
             .to_string()
             .into_bytes(),
         DEFAULT_GAS,
@@ -345,7 +388,10 @@ fn simulate_get_price_call() {
         fpo.get_price_call(
             "ETH/USD".to_string(),
             provider1.account_id(),
-            consumer.account_id()
+            consumer.account_id(),
+            None,
+            None,
+            None
         )
     );
 
@@ -362,6 +408,264 @@ fn simulate_get_price_call() {
     }
 }
 
+#[test]
+fn simulate_get_price_call_with_msg() {
+    let (root, fpo, consumer) = init();
+
+    let provider1 = root.create_user("provider1".parse().unwrap(), to_yocto("1000000"));
+    let user = root.create_user("user".parse().unwrap(), to_yocto("1000000"));
+
+    call!(root, fpo.new()).assert_success();
+    call!(root, consumer.new(fpo.account_id(), None)).assert_success();
+
+    provider1.call(
+        fpo.account_id(),
+        "create_pair",
+        &json!(["ETH/USD".to_string(), 8, U128(2000), null, null])
+            .to_string()
+            .into_bytes(),
+        DEFAULT_GAS,
+        STORAGE_COST, // attached deposit
+    );
+
+    // name the callback explicitly and pass a payload to correlate the response
+    let outcome = call!(
+        user,
+        fpo.get_price_call(
+            "ETH/USD".to_string(),
+            provider1.account_id(),
+            consumer.account_id(),
+            None,
+            Some("on_price_received".to_string()),
+            Some("liquidation:42".to_string())
+        )
+    );
+    assert!(outcome
+        .promise_results()
+        .iter()
+        .flatten()
+        .any(|result| result
+            .logs()
+            .iter()
+            .any(|log| log == "Received prices for liquidation:42")));
+
+    let fetched_entry = call!(
+        user,
+        consumer.get_pair(provider1.account_id(), "ETH/USD".to_string())
+    );
+    match &fetched_entry.promise_results()[1] {
+        Some(res) => {
+            assert_eq!(res.unwrap_json_value()["price"], "2000");
+        }
+        None => println!("Retrieved Nothing"),
+    }
+}
+
+#[test]
+fn simulate_get_entries_call() {
+    let (root, fpo, consumer) = init();
+
+    let provider1 = root.create_user("provider1".parse().unwrap(), to_yocto("1000000"));
+    let user = root.create_user("user".parse().unwrap(), to_yocto("1000000"));
+
+    call!(root, fpo.new()).assert_success();
+    call!(root, consumer.new(fpo.account_id(), None)).assert_success();
+
+    provider1.call(
+        fpo.account_id(),
+        "create_pair",
+        &json!(["ETH/USD".to_string(), 8, U128(2000), null, null])
+            .to_string()
+            .into_bytes(),
+        DEFAULT_GAS,
+        STORAGE_COST, // attached deposit
+    );
+
+    // entries are forwarded to on_entries_received() unless another method is named
+    let outcome = call!(
+        user,
+        fpo.get_entries_call(
+            vec!["ETH/USD".to_string()],
+            vec![provider1.account_id()],
+            consumer.account_id(),
+            None,
+            None,
+            Some("entries:1".to_string())
+        )
+    );
+    outcome.assert_success();
+    assert!(outcome
+        .promise_results()
+        .iter()
+        .flatten()
+        .any(|result| result
+            .logs()
+            .iter()
+            .any(|log| log == "Received entries for entries:1")));
+
+    let fetched_entry = call!(
+        user,
+        consumer.get_pair(provider1.account_id(), "ETH/USD".to_string())
+    );
+    let entry = fetched_entry.unwrap_json_value();
+    assert_eq!(entry["price"], "2000");
+    assert_eq!(entry["price_type"], "Entries");
+}
+
+#[test]
+fn simulate_request_ids() {
+    let (root, fpo, consumer) = init();
+
+    let provider1 = root.create_user("provider1".parse().unwrap(), to_yocto("1000000"));
+    let user = root.create_user("user".parse().unwrap(), to_yocto("1000000"));
+
+    call!(root, fpo.new()).assert_success();
+    call!(root, consumer.new(fpo.account_id(), None)).assert_success();
+
+    provider1.call(
+        fpo.account_id(),
+        "create_pair",
+        &json!(["ETH/USD".to_string(), 8, U128(2000), null, null])
+            .to_string()
+            .into_bytes(),
+        DEFAULT_GAS,
+        STORAGE_COST, // attached deposit
+    );
+
+    for _ in 0..2 {
+        call!(
+            user,
+            fpo.get_price_call(
+                "ETH/USD".to_string(),
+                provider1.account_id(),
+                consumer.account_id(),
+                None,
+                None,
+                None
+            )
+        )
+        .assert_success();
+    }
+
+    let last_request_id = call!(
+        user,
+        consumer.get_last_request_id(fpo.account_id(), user.account_id())
+    );
+    debug_assert_eq!(&last_request_id.unwrap_json_value(), 2);
+
+    // forwarding a price directly, without going through the oracle, is rejected
+    let outcome = call!(
+        user,
+        consumer.on_price_received(
+            user.account_id(),
+            3,
+            vec!["ETH/USD".to_string()],
+            vec![provider1.account_id()],
+            consumer::PriceType::Single,
+            vec![Some(U128(1))],
+            None
+        )
+    );
+    assert!(!outcome.is_ok());
+    assert!(format!("{:?}", outcome.status()).contains("only the oracle can forward prices"));
+
+    // replaying an old request is rejected, even from the oracle
+    let outcome = call!(
+        fpo.user_account,
+        consumer.on_price_received(
+            user.account_id(),
+            1,
+            vec!["ETH/USD".to_string()],
+            vec![provider1.account_id()],
+            consumer::PriceType::Single,
+            vec![Some(U128(1))],
+            None
+        )
+    );
+    assert!(!outcome.is_ok());
+    assert!(format!("{:?}", outcome.status()).contains("duplicate or out-of-order request id"));
+}
+
+#[test]
+fn simulate_request_ids_from_both_oracles() {
+    let (root, fpo, consumer) = init();
+    let backup: ContractAccount<FPOContractContract> = deploy! {
+        contract: FPOContractContract,
+        contract_id: "backupfpo".to_string(),
+        bytes: &FPO_BYTES,
+        signer_account: root
+    };
+
+    let provider1 = root.create_user("provider1".parse().unwrap(), to_yocto("1000000"));
+    let user = root.create_user("user".parse().unwrap(), to_yocto("1000000"));
+
+    call!(root, fpo.new()).assert_success();
+    call!(root, backup.new()).assert_success();
+    call!(
+        root,
+        consumer.new(fpo.account_id(), Some(backup.account_id()))
+    )
+    .assert_success();
+
+    for oracle in [&fpo, &backup].iter() {
+        provider1.call(
+            oracle.account_id(),
+            "create_pair",
+            &json!(["ETH/USD".to_string(), 8, U128(2000), null, null])
+                .to_string()
+                .into_bytes(),
+            DEFAULT_GAS,
+            STORAGE_COST, // attached deposit
+        );
+    }
+
+    // the primary oracle is ahead of the backup
+    for _ in 0..2 {
+        call!(
+            user,
+            fpo.get_price_call(
+                "ETH/USD".to_string(),
+                provider1.account_id(),
+                consumer.account_id(),
+                None,
+                None,
+                None
+            )
+        )
+        .assert_success();
+    }
+
+    // the backup's first request id is still accepted
+    let outcome = call!(
+        user,
+        backup.get_price_call(
+            "ETH/USD".to_string(),
+            provider1.account_id(),
+            consumer.account_id(),
+            None,
+            None,
+            None
+        )
+    );
+    outcome.assert_success();
+    assert!(outcome
+        .promise_results()
+        .iter()
+        .flatten()
+        .all(|result| result.is_ok()));
+
+    let last_request_id = call!(
+        user,
+        consumer.get_last_request_id(fpo.account_id(), user.account_id())
+    );
+    debug_assert_eq!(&last_request_id.unwrap_json_value(), 2);
+    let last_request_id = call!(
+        user,
+        consumer.get_last_request_id(backup.account_id(), user.account_id())
+    );
+    debug_assert_eq!(&last_request_id.unwrap_json_value(), 1);
+}
+
 #[test]
 fn simulate_get_prices_call() {
     let (root, fpo, consumer) = init();
@@ -372,13 +676,13 @@ fn simulate_get_prices_call() {
     let user = root.create_user("user".parse().unwrap(), to_yocto("1000000"));
 
     call!(root, fpo.new()).assert_success();
-    call!(root, consumer.new(fpo.account_id())).assert_success();
+    call!(root, consumer.new(fpo.account_id(), None)).assert_success();
 
     // create a price pair, check if it exists, and get the value
     provider1.call(
         fpo.account_id(),
         "create_pair",
-        &json!(["ETH/USD".to_string(), 8, U128(2000)])
+        &json!(["ETH/USD".to_string(), 8, U128(2000), null, null])
             .to_string()
             .into_bytes(),
         DEFAULT_GAS,
@@ -403,7 +707,7 @@ fn simulate_get_prices_call() {
     provider2.call(
         fpo.account_id(),
         "create_pair",
-        &json!(["BTC/USD".to_string(), 8, U128(45000)])
+        &json!(["BTC/USD".to_string(), 8, U128(45000), null, null])
             .to_string()
             .into_bytes(),
         DEFAULT_GAS,
@@ -429,7 +733,10 @@ fn simulate_get_prices_call() {
         fpo.get_prices_call(
             vec!["ETH/USD".to_string(), "BTC/USD".to_string()],
             vec![provider1.account_id(), provider2.account_id()],
-            consumer.account_id()
+            consumer.account_id(),
+            None,
+            None,
+            None
         )
     );
 
@@ -469,13 +776,13 @@ fn simulate_get_prices_call2() {
     let user = root.create_user("user".parse().unwrap(), to_yocto("1000000"));
 
     call!(root, fpo.new()).assert_success();
-    call!(root, consumer.new(fpo.account_id())).assert_success();
+    call!(root, consumer.new(fpo.account_id(), None)).assert_success();
 
     // create a price pair, check if it exists, and get the value
     provider1.call(
         fpo.account_id(),
         "create_pair",
-        &json!(["ETH/USD".to_string(), 8, U128(2000)])
+        &json!(["ETH/USD".to_string(), 8, U128(2000), null, null])
             .to_string()
             .into_bytes(),
         DEFAULT_GAS,
@@ -500,7 +807,7 @@ fn simulate_get_prices_call2() {
     provider2.call(
         fpo.account_id(),
         "create_pair",
-        &json!(["ETH/USD".to_string(), 8, U128(4000)])
+        &json!(["ETH/USD".to_string(), 8, U128(4000), null, null])
             .to_string()
             .into_bytes(),
         DEFAULT_GAS,
@@ -526,7 +833,10 @@ fn simulate_get_prices_call2() {
         fpo.get_prices_call(
             vec!["ETH/USD".to_string(), "ETH/USD".to_string()],
             vec![provider1.account_id(), provider2.account_id()],
-            consumer.account_id()
+            consumer.account_id(),
+            None,
+            None,
+            None
         )
     );
 
@@ -565,13 +875,13 @@ fn simulate_aggregate_avg_call() {
     let user = root.create_user("user".parse().unwrap(), to_yocto("1000000"));
 
     call!(root, fpo.new()).assert_success();
-    call!(root, consumer.new(fpo.account_id())).assert_success();
+    call!(root, consumer.new(fpo.account_id(), None)).assert_success();
 
     // create a price pair, check if it exists, and get the value
     provider1.call(
         fpo.account_id(),
         "create_pair",
-        &json!(["ETH/USD".to_string(), 8, U128(2000)])
+        &json!(["ETH/USD".to_string(), 8, U128(2000), null, null])
             .to_string()
             .into_bytes(),
         DEFAULT_GAS,
@@ -596,7 +906,7 @@ fn simulate_aggregate_avg_call() {
     provider2.call(
         fpo.account_id(),
         "create_pair",
-        &json!(["ETH / USD".to_string(), 8, U128(4000)])
+        &json!(["ETH / USD".to_string(), 8, U128(4000), null, null])
             .to_string()
             .into_bytes(),
         DEFAULT_GAS,
@@ -623,7 +933,11 @@ fn simulate_aggregate_avg_call() {
             vec!["ETH/USD".to_string(), "ETH / USD".to_string()],
             vec![provider1.account_id(), provider2.account_id()],
             0,
-            consumer.account_id()
+            consumer.account_id(),
+            None,
+            None,
+            None,
+            None
         )
     );
 
@@ -662,13 +976,13 @@ fn simulate_aggregate_median_call() {
     let user = root.create_user("user".parse().unwrap(), to_yocto("1000000"));
 
     call!(root, fpo.new()).assert_success();
-    call!(root, consumer.new(fpo.account_id())).assert_success();
+    call!(root, consumer.new(fpo.account_id(), None)).assert_success();
 
     // create a price pair, check if it exists, and get the value
     provider1.call(
         fpo.account_id(),
         "create_pair",
-        &json!(["ETH/USD".to_string(), 8, U128(2000)])
+        &json!(["ETH/USD".to_string(), 8, U128(2000), null, null])
             .to_string()
             .into_bytes(),
         DEFAULT_GAS,
@@ -693,7 +1007,7 @@ fn simulate_aggregate_median_call() {
     provider2.call(
         fpo.account_id(),
         "create_pair",
-        &json!(["ETH / USD".to_string(), 8, U128(4000)])
+        &json!(["ETH / USD".to_string(), 8, U128(4000), null, null])
             .to_string()
             .into_bytes(),
         DEFAULT_GAS,
@@ -720,7 +1034,11 @@ fn simulate_aggregate_median_call() {
             vec!["ETH/USD".to_string(), "ETH / USD".to_string()],
             vec![provider1.account_id(), provider2.account_id()],
             0,
-            consumer.account_id()
+            consumer.account_id(),
+            None,
+            None,
+            None,
+            None
         )
     );
 
@@ -759,13 +1077,13 @@ fn simulate_aggregate_median_many_call() {
     let user = root.create_user("user".parse().unwrap(), to_yocto("1000000"));
 
     call!(root, fpo.new()).assert_success();
-    call!(root, consumer.new(fpo.account_id())).assert_success();
+    call!(root, consumer.new(fpo.account_id(), None)).assert_success();
 
     // create eth/usd and btc/usd from provider1
     provider1.call(
         fpo.account_id(),
         "create_pair",
-        &json!(["ETH/USD".to_string(), 8, U128(2000)])
+        &json!(["ETH/USD".to_string(), 8, U128(2000), null, null])
             .to_string()
             .into_bytes(),
         DEFAULT_GAS,
@@ -774,7 +1092,7 @@ fn simulate_aggregate_median_many_call() {
     provider1.call(
         fpo.account_id(),
         "create_pair",
-        &json!(["BTC/USD".to_string(), 8, U128(30000)])
+        &json!(["BTC/USD".to_string(), 8, U128(30000), null, null])
             .to_string()
             .into_bytes(),
         DEFAULT_GAS,
@@ -785,7 +1103,7 @@ fn simulate_aggregate_median_many_call() {
     provider2.call(
         fpo.account_id(),
         "create_pair",
-        &json!(["ETH/USD".to_string(), 8, U128(4000)])
+        &json!(["ETH/USD".to_string(), 8, U128(4000), null, null])
             .to_string()
             .into_bytes(),
         DEFAULT_GAS,
@@ -794,7 +1112,7 @@ fn simulate_aggregate_median_many_call() {
     provider2.call(
         fpo.account_id(),
         "create_pair",
-        &json!(["BTC/USD".to_string(), 8, U128(40000)])
+        &json!(["BTC/USD".to_string(), 8, U128(40000), null, null])
             .to_string()
             .into_bytes(),
         DEFAULT_GAS,
@@ -811,7 +1129,11 @@ fn simulate_aggregate_median_many_call() {
             vec![pairs_eth, pairs_btc],
             vec![providers.clone(), providers],
             0,
-            consumer.account_id()
+            consumer.account_id(),
+            None,
+            None,
+            None,
+            None
         )
     );
 
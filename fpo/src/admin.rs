@@ -0,0 +1,325 @@
+use crate::*;
+use near_sdk::serde::{Deserialize, Serialize};
+
+#[derive(
+    BorshDeserialize, BorshSerialize, Deserialize, Serialize, Debug, Clone, Copy, PartialEq,
+)]
+pub enum Role {
+    Admin,           // Can grant and revoke the other roles
+    Pauser,          // Can pause and unpause the contract
+    ProviderManager, // Can manage providers
+    Arbiter,         // Can resolve disputes
+}
+
+/// Access control methods (internal)
+impl FPOContract {
+    /// Panics if the predecessor is not the owner
+    pub fn assert_owner(&self) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.owner,
+            "only the owner can call this method"
+        );
+    }
+
+    /// Panics if the predecessor doesn't have the given role
+    pub fn assert_role(&self, role: Role) {
+        assert!(
+            self.has_role(env::predecessor_account_id(), role),
+            "missing role {:?}",
+            role
+        );
+    }
+
+    /// Panics if the contract is paused
+    pub fn assert_not_paused(&self) {
+        assert!(!self.paused, "contract is paused");
+    }
+}
+
+/// Public contract methods
+#[near_bindgen]
+impl FPOContract {
+    /// Returns the current owner
+    pub fn get_owner(&self) -> AccountId {
+        self.owner.clone()
+    }
+
+    /// Returns the account that has been proposed as the new owner, if any
+    pub fn get_pending_owner(&self) -> Option<AccountId> {
+        self.pending_owner.clone()
+    }
+
+    /// Proposes a new owner, who has to call `accept_ownership()` to complete the transfer
+    pub fn transfer_ownership(&mut self, new_owner: AccountId) {
+        self.assert_owner();
+        self.pending_owner = Some(new_owner);
+    }
+
+    /// Completes an ownership transfer started by `transfer_ownership()`
+    pub fn accept_ownership(&mut self) {
+        let pending_owner = self
+            .pending_owner
+            .take()
+            .expect("no ownership transfer pending");
+        assert_eq!(
+            env::predecessor_account_id(),
+            pending_owner,
+            "only the pending owner can accept ownership"
+        );
+        self.owner = pending_owner;
+    }
+
+    /// Checks if an account has a role, the owner implicitly has every role
+    pub fn has_role(&self, account_id: AccountId, role: Role) -> bool {
+        account_id == self.owner
+            || self
+                .roles
+                .get(&account_id)
+                .map(|roles| roles.contains(&role))
+                .unwrap_or(false)
+    }
+
+    /// Returns the roles explicitly granted to an account
+    pub fn get_roles(&self, account_id: AccountId) -> Vec<Role> {
+        self.roles.get(&account_id).unwrap_or_default()
+    }
+
+    /// Grants a role to an account, only the owner can grant the admin role
+    pub fn grant_role(&mut self, account_id: AccountId, role: Role) {
+        if role == Role::Admin {
+            self.assert_owner();
+        } else {
+            self.assert_role(Role::Admin);
+        }
+
+        let mut roles = self.roles.get(&account_id).unwrap_or_default();
+        if !roles.contains(&role) {
+            roles.push(role);
+            self.roles.insert(&account_id, &roles);
+        }
+    }
+
+    /// Revokes a role from an account, only the owner can revoke the admin role
+    pub fn revoke_role(&mut self, account_id: AccountId, role: Role) {
+        if role == Role::Admin {
+            self.assert_owner();
+        } else {
+            self.assert_role(Role::Admin);
+        }
+
+        let mut roles = self.roles.get(&account_id).unwrap_or_default();
+        roles.retain(|r| *r != role);
+        if roles.is_empty() {
+            self.roles.remove(&account_id);
+        } else {
+            self.roles.insert(&account_id, &roles);
+        }
+    }
+
+    /// Pauses `create_pair` and `push_data`, views keep working
+    pub fn pause(&mut self) {
+        self.assert_role(Role::Pauser);
+        self.paused = true;
+    }
+
+    /// Lifts a pause
+    pub fn unpause(&mut self) {
+        self.assert_role(Role::Pauser);
+        self.paused = false;
+    }
+
+    /// Checks if the contract is paused
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+}
+
+/// Access control tests
+#[cfg(test)]
+mod tests {
+
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+    use price_pair::STORAGE_COST;
+
+    use super::*;
+
+    fn alice() -> AccountId {
+        "alice.near".parse().unwrap()
+    }
+    fn bob() -> AccountId {
+        "bob.near".parse().unwrap()
+    }
+    fn carol() -> AccountId {
+        "carol.near".parse().unwrap()
+    }
+
+    fn get_context(
+        predecessor_account_id: AccountId,
+        current_account_id: AccountId,
+    ) -> VMContextBuilder {
+        let mut builder = VMContextBuilder::new();
+        builder
+            .current_account_id(current_account_id.clone())
+            .signer_account_id("robert.testnet".parse().unwrap())
+            .predecessor_account_id(predecessor_account_id.clone())
+            .attached_deposit(STORAGE_COST);
+        builder
+    }
+
+    /// Creates a contract owned by alice
+    fn setup() -> FPOContract {
+        let context = get_context(alice(), alice());
+        testing_env!(context.build());
+        FPOContract::new()
+    }
+
+    #[test]
+    fn owner_is_set_at_init() {
+        let fpo_contract = setup();
+        assert_eq!(alice(), fpo_contract.get_owner());
+        assert_eq!(None, fpo_contract.get_pending_owner());
+        assert!(fpo_contract.has_role(alice(), Role::Admin));
+        assert!(fpo_contract.has_role(alice(), Role::Pauser));
+        assert!(fpo_contract.has_role(alice(), Role::ProviderManager));
+        assert!(!fpo_contract.has_role(bob(), Role::Pauser));
+    }
+
+    #[test]
+    fn transfer_ownership() {
+        let mut fpo_contract = setup();
+        fpo_contract.transfer_ownership(bob());
+        assert_eq!(alice(), fpo_contract.get_owner());
+        assert_eq!(Some(bob()), fpo_contract.get_pending_owner());
+
+        testing_env!(get_context(bob(), alice()).build());
+        fpo_contract.accept_ownership();
+        assert_eq!(bob(), fpo_contract.get_owner());
+        assert_eq!(None, fpo_contract.get_pending_owner());
+        assert!(!fpo_contract.has_role(alice(), Role::Admin));
+    }
+
+    #[test]
+    #[should_panic(expected = "only the owner can call this method")]
+    fn transfer_ownership_not_owner() {
+        let mut fpo_contract = setup();
+        testing_env!(get_context(bob(), alice()).build());
+        fpo_contract.transfer_ownership(bob());
+    }
+
+    #[test]
+    #[should_panic(expected = "only the pending owner can accept ownership")]
+    fn accept_ownership_not_pending_owner() {
+        let mut fpo_contract = setup();
+        fpo_contract.transfer_ownership(bob());
+        testing_env!(get_context(carol(), alice()).build());
+        fpo_contract.accept_ownership();
+    }
+
+    #[test]
+    #[should_panic(expected = "no ownership transfer pending")]
+    fn accept_ownership_without_transfer() {
+        let mut fpo_contract = setup();
+        testing_env!(get_context(bob(), alice()).build());
+        fpo_contract.accept_ownership();
+    }
+
+    #[test]
+    fn admin_grants_and_revokes_roles() {
+        let mut fpo_contract = setup();
+        fpo_contract.grant_role(bob(), Role::Admin);
+
+        testing_env!(get_context(bob(), alice()).build());
+        fpo_contract.grant_role(carol(), Role::Pauser);
+        fpo_contract.grant_role(carol(), Role::ProviderManager);
+        assert_eq!(
+            vec![Role::Pauser, Role::ProviderManager],
+            fpo_contract.get_roles(carol())
+        );
+
+        fpo_contract.revoke_role(carol(), Role::Pauser);
+        assert!(!fpo_contract.has_role(carol(), Role::Pauser));
+        assert!(fpo_contract.has_role(carol(), Role::ProviderManager));
+    }
+
+    #[test]
+    #[should_panic(expected = "only the owner can call this method")]
+    fn admin_cannot_grant_admin() {
+        let mut fpo_contract = setup();
+        fpo_contract.grant_role(bob(), Role::Admin);
+        testing_env!(get_context(bob(), alice()).build());
+        fpo_contract.grant_role(carol(), Role::Admin);
+    }
+
+    #[test]
+    #[should_panic(expected = "missing role Admin")]
+    fn pauser_cannot_grant_roles() {
+        let mut fpo_contract = setup();
+        fpo_contract.grant_role(bob(), Role::Pauser);
+        testing_env!(get_context(bob(), alice()).build());
+        fpo_contract.grant_role(carol(), Role::Pauser);
+    }
+
+    #[test]
+    #[should_panic(expected = "missing role Pauser")]
+    fn provider_manager_cannot_pause() {
+        let mut fpo_contract = setup();
+        fpo_contract.grant_role(bob(), Role::ProviderManager);
+        testing_env!(get_context(bob(), alice()).build());
+        fpo_contract.pause();
+    }
+
+    #[test]
+    #[should_panic(expected = "missing role Pauser")]
+    fn admin_cannot_pause() {
+        let mut fpo_contract = setup();
+        fpo_contract.grant_role(bob(), Role::Admin);
+        testing_env!(get_context(bob(), alice()).build());
+        fpo_contract.pause();
+    }
+
+    #[test]
+    fn pauser_pauses_and_unpauses() {
+        let mut fpo_contract = setup();
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2500), None, None);
+        fpo_contract.grant_role(bob(), Role::Pauser);
+
+        testing_env!(get_context(bob(), alice()).build());
+        fpo_contract.pause();
+        assert!(fpo_contract.is_paused());
+
+        // views keep working while paused
+        assert_eq!(
+            Some(U128(2500)),
+            fpo_contract.get_price("ETH/USD".to_string(), &alice())
+        );
+
+        fpo_contract.unpause();
+        assert!(!fpo_contract.is_paused());
+
+        testing_env!(get_context(alice(), alice()).build());
+        fpo_contract.push_data("ETH/USD".to_string(), U128(3000), None);
+        assert_eq!(
+            Some(U128(3000)),
+            fpo_contract.get_price("ETH/USD".to_string(), &alice())
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "contract is paused")]
+    fn push_data_while_paused() {
+        let mut fpo_contract = setup();
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2500), None, None);
+        fpo_contract.pause();
+        fpo_contract.push_data("ETH/USD".to_string(), U128(3000), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "contract is paused")]
+    fn create_pair_while_paused() {
+        let mut fpo_contract = setup();
+        fpo_contract.pause();
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2500), None, None);
+    }
+}
@@ -1,88 +1,201 @@
 use crate::*;
 // use near_account_id::AccountId;
+use math::Rounding;
+use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::Timestamp;
-/// Public contract methods
-#[near_bindgen]
+use price_pair::PriceEntry;
+use provider::{Provider, ProviderStatus};
+
+/// Optional filters applied to the entries of an aggregation
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct AggregateOptions {
+    pub active_only: bool,      // Leave out providers that are not active
+    pub use_heartbeat: bool,    // Leave out entries that missed their own heartbeat instead of using `min_last_update`
+    pub min_score: Option<u16>, // Leave out providers with a lower score for the pair
+    pub bond_weighted: bool,    // Weigh prices by the bond of their provider
+    pub exclude_disputed: bool, // Leave out entries whose latest round has an open or upheld dispute
+}
+
+/// Private contract methods
 impl FPOContract {
-    /// Returns the mean of given price pairs from given providers
-    pub fn aggregate_avg(
+    /// Returns the price of every given pair and provider, or None if the entry is
+    /// older than `min_last_update` or filtered out by `options`
+    pub fn collect_prices(
         &self,
-        pairs: Vec<String>,
-        providers: Vec<AccountId>,
+        pairs: &[String],
+        providers: &[AccountId],
         min_last_update: Timestamp,
-    ) -> Option<U128> {
+        options: &AggregateOptions,
+    ) -> Vec<Option<u128>> {
+        self.collect_weighted_prices(pairs, providers, min_last_update, options)
+            .into_iter()
+            .map(|price| price.map(|(price, _)| price))
+            .collect()
+    }
+
+    /// Returns the price and bond of every given pair and provider, or None if the entry is
+    /// older than `min_last_update` or filtered out by `options`. With `use_heartbeat`,
+    /// pairs that have a heartbeat are checked against it and the others against `min_last_update`
+    pub fn collect_weighted_prices(
+        &self,
+        pairs: &[String],
+        providers: &[AccountId],
+        min_last_update: Timestamp,
+        options: &AggregateOptions,
+    ) -> Vec<Option<(u128, u128)>> {
         assert_eq!(
             pairs.len(),
             providers.len(),
             "pairs and provider should be of equal length"
         );
 
-        let min_last_update: u64 = min_last_update;
-        let mut amount_of_providers = providers.len();
-
-        let cumulative = providers
+        providers
             .iter()
             .zip(pairs.iter())
-            .fold(0, |s, (account_id, pair)| {
+            .map(|(account_id, pair)| {
                 let provider = self.get_provider_expect(account_id);
-                let pair_name = format!("{}:{}", pair, account_id);
-                let entry = provider.get_entry_expect(&pair_name);
-
-                // If this entry was updated after the min_last_update take it out of the average
-                if entry.last_update < min_last_update {
-                    amount_of_providers -= 1;
-                    s
-                } else {
-                    s + u128::from(entry.price)
-                }
-            });
-
-        if amount_of_providers as u128 == 0 {
-            return None;
-        }
-
-        Some(U128::from(cumulative / amount_of_providers as u128))
+                let entry = provider.get_entry_expect(&format!("{}:{}", pair, account_id));
+                self.filter_entry(&provider, account_id, pair, entry, min_last_update, options)
+                    .map(|entry| (u128::from(entry.price), provider.bond))
+            })
+            .collect()
     }
 
-    /// Returns the median of given price pairs from given providers
-    pub fn aggregate_median(
+    /// Returns the entry and bond of every given pair and provider like
+    /// `collect_weighted_prices()`, or None if the provider or entry doesn't exist
+    pub fn collect_entries(
         &self,
-        pairs: Vec<String>,
-        providers: Vec<AccountId>,
+        pairs: &[String],
+        providers: &[AccountId],
         min_last_update: Timestamp,
-    ) -> Option<U128> {
+        options: &AggregateOptions,
+    ) -> Vec<Option<(PriceEntry, u128)>> {
         assert_eq!(
             pairs.len(),
             providers.len(),
             "pairs and provider should be of equal length"
         );
 
-        let min_last_update: u64 = min_last_update;
-        let mut amount_of_providers = providers.len();
+        providers
+            .iter()
+            .zip(pairs.iter())
+            .map(|(account_id, pair)| {
+                let provider = self.get_provider_option(account_id)?;
+                let entry = provider.get_entry_option(&format!("{}:{}", pair, account_id))?;
+                self.filter_entry(&provider, account_id, pair, entry, min_last_update, options)
+                    .map(|entry| (entry, provider.bond))
+            })
+            .collect()
+    }
 
-        let mut cumulative = providers.iter().zip(pairs.iter()).fold(
-            vec![],
-            |mut arr: Vec<u128>, (account_id, pair)| {
-                let provider = self.get_provider_expect(account_id);
-                let pair_name = format!("{}:{}", pair, account_id);
-                let entry = provider.get_entry_expect(&pair_name);
-
-                // If this entry was updated after the min_last_update take it out of the average
-                if entry.last_update < min_last_update {
-                    amount_of_providers -= 1;
-                    arr
-                } else {
-                    arr.push(u128::from(entry.price));
-                    arr
-                }
-            },
-        );
+    /// Returns the entry of a pair by a provider, or None if it is older than `min_last_update`
+    /// or filtered out by `options`
+    fn filter_entry(
+        &self,
+        provider: &Provider,
+        account_id: &AccountId,
+        pair: &str,
+        entry: PriceEntry,
+        min_last_update: Timestamp,
+        options: &AggregateOptions,
+    ) -> Option<PriceEntry> {
+        if options.active_only && provider.status != ProviderStatus::Active {
+            return None;
+        }
 
-        if cumulative.is_empty() {
+        let pair_name = format!("{}:{}", pair, account_id);
+        if let Some(min_score) = options.min_score {
+            if provider.get_score(&pair_name) < min_score {
+                return None;
+            }
+        }
+        // prices of commit-reveal pairs only count once they are revealed
+        if !entry.is_revealed(env::block_timestamp()) {
             return None;
         }
+        if options.exclude_disputed && self.is_round_disputed(&pair_name, entry.round_id) {
+            return None;
+        }
+
+        // If this entry was updated after the min_last_update take it out of the aggregation
+        let outdated = if options.use_heartbeat && entry.config.heartbeat.is_some() {
+            entry.is_stale(env::block_timestamp())
+        } else {
+            entry.last_update < min_last_update
+        };
+        if outdated {
+            None
+        } else {
+            Some(entry)
+        }
+    }
+}
+
+/// Returns the prices and bonds of entries in `decimals`, leaving out prices that don't fit
+pub fn rescale_prices(entries: &[(PriceEntry, u128)], decimals: u16) -> Vec<(u128, u128)> {
+    entries
+        .iter()
+        .filter_map(|(entry, bond)| {
+            math::rescale(entry.price.0, entry.decimals, decimals, Rounding::Down)
+                .map(|price| (price, *bond))
+        })
+        .collect()
+}
+
+/// Returns the mean of (price, bond) pairs, weighted by bond with `bond_weighted`
+pub fn mean_price(prices: &[(u128, u128)], bond_weighted: bool) -> Option<U128> {
+    if prices.is_empty() {
+        return None;
+    }
+    if bond_weighted {
+        return math::weighted_mean(prices);
+    }
+    let prices: Vec<u128> = prices.iter().map(|(price, _)| *price).collect();
+    Some(math::mean(&prices))
+}
 
-        Some(math::median(&mut cumulative))
+/// Returns the median of (price, bond) pairs, weighted by bond with `bond_weighted`
+pub fn median_price(prices: &mut [(u128, u128)], bond_weighted: bool) -> Option<U128> {
+    if prices.is_empty() {
+        return None;
+    }
+    if bond_weighted {
+        return math::weighted_median(prices);
+    }
+    let mut prices: Vec<u128> = prices.iter().map(|(price, _)| *price).collect();
+    Some(math::median(&mut prices))
+}
+
+/// Public contract methods
+#[near_bindgen]
+impl FPOContract {
+    /// Returns the mean of given price pairs from given providers
+    pub fn aggregate_avg(
+        &self,
+        pairs: Vec<String>,
+        providers: Vec<AccountId>,
+        min_last_update: Timestamp,
+        options: Option<AggregateOptions>,
+    ) -> Option<U128> {
+        let options = options.unwrap_or_default();
+        let prices = self.collect_weighted_prices(&pairs, &providers, min_last_update, &options);
+        let prices: Vec<(u128, u128)> = prices.into_iter().flatten().collect();
+        mean_price(&prices, options.bond_weighted)
+    }
+
+    /// Returns the median of given price pairs from given providers
+    pub fn aggregate_median(
+        &self,
+        pairs: Vec<String>,
+        providers: Vec<AccountId>,
+        min_last_update: Timestamp,
+        options: Option<AggregateOptions>,
+    ) -> Option<U128> {
+        let options = options.unwrap_or_default();
+        let prices = self.collect_weighted_prices(&pairs, &providers, min_last_update, &options);
+        let mut prices: Vec<(u128, u128)> = prices.into_iter().flatten().collect();
+        median_price(&mut prices, options.bond_weighted)
     }
 
     /// Returns multiple prices given by specified pairs and providers
@@ -91,32 +204,17 @@ impl FPOContract {
         pairs: Vec<String>,
         providers: Vec<AccountId>,
         min_last_update: Timestamp,
+        options: Option<AggregateOptions>,
     ) -> Vec<Option<U128>> {
-        assert_eq!(
-            pairs.len(),
-            providers.len(),
-            "pairs and provider should be of equal length"
-        );
-        let min_last_update: u64 = min_last_update;
-        providers // Was pairs??
-            .iter()
-            .zip(pairs.iter())
-            .map(|(account_id, pair)| {
-                let provider = self
-                    .providers
-                    .get(account_id)
-                    .expect("no provider with account id");
-                let pair_name = format!("{}:{}", pair, account_id);
-                let entry = provider.get_entry_expect(&pair_name);
-
-                // If this entry was updated after the min_last_update take it out of the average
-                if entry.last_update < min_last_update {
-                    None
-                } else {
-                    Some(entry.price)
-                }
-            })
-            .collect()
+        self.collect_prices(
+            &pairs,
+            &providers,
+            min_last_update,
+            &options.unwrap_or_default(),
+        )
+        .into_iter()
+        .map(|price| price.map(U128))
+        .collect()
     }
 
     /// Wrapper around `aggregate_avg` to return the average prices of multiple pairs
@@ -125,6 +223,7 @@ impl FPOContract {
         pairs: Vec<Vec<String>>,
         providers: Vec<Vec<AccountId>>,
         min_last_update: Timestamp,
+        options: Option<AggregateOptions>,
     ) -> Vec<Option<U128>> {
         assert_eq!(
             pairs.len(),
@@ -135,7 +234,14 @@ impl FPOContract {
         pairs
             .iter()
             .zip(providers.iter())
-            .map(|(pairs, providers)| self.aggregate_avg(pairs.to_vec(), providers.to_vec(), min_last_update))
+            .map(|(pairs, providers)| {
+                self.aggregate_avg(
+                    pairs.to_vec(),
+                    providers.to_vec(),
+                    min_last_update,
+                    options.clone(),
+                )
+            })
             .collect()
     }
 
@@ -145,6 +251,7 @@ impl FPOContract {
         pairs: Vec<Vec<String>>,
         providers: Vec<Vec<AccountId>>,
         min_last_update: Timestamp,
+        options: Option<AggregateOptions>,
     ) -> Vec<Option<U128>> {
         assert_eq!(
             pairs.len(),
@@ -155,7 +262,14 @@ impl FPOContract {
         pairs
             .iter()
             .zip(providers.iter())
-            .map(|(pairs, providers)| self.aggregate_median(pairs.to_vec(), providers.to_vec(), min_last_update))
+            .map(|(pairs, providers)| {
+                self.aggregate_median(
+                    pairs.to_vec(),
+                    providers.to_vec(),
+                    min_last_update,
+                    options.clone(),
+                )
+            })
             .collect()
     }
 
@@ -165,6 +279,7 @@ impl FPOContract {
         pairs: Vec<Vec<String>>,
         providers: Vec<Vec<AccountId>>,
         min_last_update: Timestamp,
+        options: Option<AggregateOptions>,
     ) -> Vec<Vec<Option<U128>>> {
         assert_eq!(
             pairs.len(),
@@ -175,7 +290,14 @@ impl FPOContract {
         pairs
             .iter()
             .zip(providers.iter())
-            .map(|(pairs, providers)| self.aggregate_collect(pairs.to_vec(), providers.to_vec(), min_last_update))
+            .map(|(pairs, providers)| {
+                self.aggregate_collect(
+                    pairs.to_vec(),
+                    providers.to_vec(),
+                    min_last_update,
+                    options.clone(),
+                )
+            })
             .collect()
     }
 }
@@ -186,7 +308,7 @@ mod tests {
 
     use near_sdk::test_utils::VMContextBuilder;
     use near_sdk::testing_env;
-    use price_pair::STORAGE_COST;
+    use price_pair::{PairConfig, STORAGE_COST};
 
     use super::*;
 
@@ -224,25 +346,25 @@ mod tests {
 
         // instantiate a contract variable
         let mut fpo_contract = FPOContract::new();
-        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2000));
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2000), None, None);
 
         // switch to bob as signer
         context = get_context(bob(), bob());
         testing_env!(context.build());
 
-        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(4000));
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(4000), None, None);
 
         // switch to carol as signer
         context = get_context(carol(), carol());
         testing_env!(context.build());
 
-        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(4000));
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(4000), None, None);
 
         // switch to dina as signer
         context = get_context(dina(), dina());
         testing_env!(context.build());
 
-        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(4000));
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(4000), None, None);
 
         assert_eq!(
             U128(2000),
@@ -283,7 +405,7 @@ mod tests {
         ];
         assert_eq!(
             Some(U128(3500)),
-            fpo_contract.aggregate_avg(pairs, vec![alice(), bob(), carol(), dina()], 0)
+            fpo_contract.aggregate_avg(pairs, vec![alice(), bob(), carol(), dina()], 0, None)
         );
     }
 
@@ -295,25 +417,25 @@ mod tests {
 
         // instantiate a contract variable
         let mut fpo_contract = FPOContract::new();
-        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2000));
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2000), None, None);
 
         // switch to bob as signer
         context = get_context(bob(), bob());
         testing_env!(context.build());
 
-        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2000));
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2000), None, None);
 
         // switch to carol as signer
         context = get_context(carol(), carol());
         testing_env!(context.build());
 
-        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(4000));
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(4000), None, None);
 
         // switch to dina as signer
         context = get_context(dina(), dina());
         testing_env!(context.build());
 
-        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(4000));
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(4000), None, None);
 
         assert_eq!(
             U128(2000),
@@ -354,7 +476,7 @@ mod tests {
         ];
         assert_eq!(
             Some(U128(3000)),
-            fpo_contract.aggregate_median(pairs, vec![alice(), bob(), carol(), dina()], 0)
+            fpo_contract.aggregate_median(pairs, vec![alice(), bob(), carol(), dina()], 0, None)
         );
     }
 
@@ -366,29 +488,29 @@ mod tests {
 
         // instantiate a contract variable
         let mut fpo_contract = FPOContract::new();
-        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2000));
-        fpo_contract.create_pair("BTC/USD".to_string(), 8, U128(30000));
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2000), None, None);
+        fpo_contract.create_pair("BTC/USD".to_string(), 8, U128(30000), None, None);
         
         // switch to bob as signer
         context = get_context(bob(), bob());
         testing_env!(context.build());
         
-        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2000));
-        fpo_contract.create_pair("BTC/USD".to_string(), 8, U128(30000));
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2000), None, None);
+        fpo_contract.create_pair("BTC/USD".to_string(), 8, U128(30000), None, None);
         
         // switch to carol as signer
         context = get_context(carol(), carol());
         testing_env!(context.build());
         
-        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(4000));
-        fpo_contract.create_pair("BTC/USD".to_string(), 8, U128(40000));
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(4000), None, None);
+        fpo_contract.create_pair("BTC/USD".to_string(), 8, U128(40000), None, None);
         
         // switch to dina as signer
         context = get_context(dina(), dina());
         testing_env!(context.build());
         
-        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(4000));
-        fpo_contract.create_pair("BTC/USD".to_string(), 8, U128(40000));
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(4000), None, None);
+        fpo_contract.create_pair("BTC/USD".to_string(), 8, U128(40000), None, None);
 
         let pairs_eth = vec![
             "ETH/USD".to_string(),
@@ -410,8 +532,75 @@ mod tests {
         ];
         assert_eq!(
             vec![Some(U128(3000)), Some(U128(35000))],
-            fpo_contract.aggregate_median_many(vec![pairs_eth, pairs_btc], vec![providers.clone(), providers], 0)
+            fpo_contract.aggregate_median_many(vec![pairs_eth, pairs_btc], vec![providers.clone(), providers], 0, None)
         );
     }
 
+    #[test]
+    fn aggregate_avg_heartbeat() {
+        let mut context = get_context(alice(), alice());
+        testing_env!(context.block_timestamp(100).build());
+        let mut fpo_contract = FPOContract::new();
+        fpo_contract.create_pair(
+            "ETH/USD".to_string(),
+            8,
+            U128(2000),
+            Some(PairConfig {
+                heartbeat: Some(50),
+                deviation_threshold: None,
+            }),
+            None,
+        );
+
+        context = get_context(bob(), bob());
+        testing_env!(context.block_timestamp(100).build());
+        fpo_contract.create_pair(
+            "ETH/USD".to_string(),
+            8,
+            U128(4000),
+            Some(PairConfig {
+                heartbeat: Some(500),
+                deviation_threshold: None,
+            }),
+            None,
+        );
+
+        context = get_context(carol(), carol());
+        testing_env!(context.block_timestamp(100).build());
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(6000), None, None);
+
+        // alice missed her heartbeat, carol falls back to min_last_update
+        testing_env!(context.block_timestamp(200).build());
+        let pairs = vec![
+            "ETH/USD".to_string(),
+            "ETH/USD".to_string(),
+            "ETH/USD".to_string(),
+        ];
+        let options = AggregateOptions {
+            use_heartbeat: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            Some(U128(5000)),
+            fpo_contract.aggregate_avg(
+                pairs.clone(),
+                vec![alice(), bob(), carol()],
+                0,
+                Some(options.clone())
+            )
+        );
+        assert_eq!(
+            Some(U128(4000)),
+            fpo_contract.aggregate_avg(
+                pairs.clone(),
+                vec![alice(), bob(), carol()],
+                150,
+                Some(options)
+            )
+        );
+        assert_eq!(
+            Some(U128(4000)),
+            fpo_contract.aggregate_avg(pairs, vec![alice(), bob(), carol()], 0, None)
+        );
+    }
 }
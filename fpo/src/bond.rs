@@ -0,0 +1,302 @@
+use crate::*;
+use admin::Role;
+use events::{FPOEvent, ProviderSlashedData};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{Promise, Timestamp};
+
+/// Time a provider has to wait between unbonding and withdrawing, 7 days in nanoseconds
+pub const DEFAULT_UNBONDING_DELAY: u64 = 7 * 24 * 60 * 60 * 1_000_000_000;
+
+/// Part of a bond that is waiting for the unbonding delay to pass
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Unbonding {
+    pub amount: U128,
+    pub available_at: Timestamp,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct BondInfo {
+    pub bond: U128,
+    pub unbonding: Option<Unbonding>,
+}
+
+/// Private contract methods
+impl FPOContract {
+    /// Takes up to `amount` from a provider's bond and unbonding funds, returning the slashed amount
+    pub fn slash_bond(&mut self, account_id: &AccountId, amount: u128) -> u128 {
+        let mut provider = self.get_provider_expect(account_id);
+
+        let from_bond = amount.min(provider.bond);
+        provider.bond -= from_bond;
+        let mut slashed = from_bond;
+
+        // funds that are still unbonding can be slashed as well
+        if let Some(mut unbonding) = provider.unbonding.take() {
+            let from_unbonding = (amount - slashed).min(unbonding.amount.0);
+            unbonding.amount = U128(unbonding.amount.0 - from_unbonding);
+            slashed += from_unbonding;
+            if unbonding.amount.0 > 0 {
+                provider.unbonding = Some(unbonding);
+            }
+        }
+        self.set_provider(account_id, provider);
+
+        FPOEvent::ProviderSlashed(vec![ProviderSlashedData {
+            provider: account_id,
+            amount: U128(slashed),
+        }])
+        .emit();
+        slashed
+    }
+}
+
+/// Public contract methods
+#[near_bindgen]
+impl FPOContract {
+    /// Adds the attached deposit to the bond of the calling provider
+    #[payable]
+    pub fn bond(&mut self) {
+        self.assert_not_paused();
+        assert!(env::attached_deposit() > 0, "attach a deposit to bond");
+
+        let mut provider = match self.get_provider_option(&env::predecessor_account_id()) {
+            Some(provider) => provider,
+            None => {
+                assert!(!self.permissioned, "provider is not registered");
+                Provider::new()
+            }
+        };
+        provider.bond += env::attached_deposit();
+        self.set_provider(&env::predecessor_account_id(), provider);
+    }
+
+    /// Starts unbonding part of the bond, which can be withdrawn after the unbonding delay.
+    /// Unbonding more while an unbonding is pending restarts the delay for the total amount
+    pub fn unbond(&mut self, amount: U128) {
+        let mut provider = self.get_provider_expect(&env::predecessor_account_id());
+        assert!(amount.0 <= provider.bond, "amount exceeds the bond");
+
+        provider.bond -= amount.0;
+        let pending = provider
+            .unbonding
+            .take()
+            .map(|unbonding| unbonding.amount.0)
+            .unwrap_or(0);
+        provider.unbonding = Some(Unbonding {
+            amount: U128(pending + amount.0),
+            available_at: env::block_timestamp() + self.unbonding_delay,
+        });
+        self.set_provider(&env::predecessor_account_id(), provider);
+    }
+
+    /// Transfers unbonded funds back to the calling provider once the unbonding delay passed
+    pub fn withdraw_unbonded(&mut self) -> Promise {
+        let mut provider = self.get_provider_expect(&env::predecessor_account_id());
+        let unbonding = provider.unbonding.take().expect("nothing to withdraw");
+        assert!(
+            env::block_timestamp() >= unbonding.available_at,
+            "unbonding delay has not passed"
+        );
+        self.set_provider(&env::predecessor_account_id(), provider);
+
+        Promise::new(env::predecessor_account_id()).transfer(unbonding.amount.0)
+    }
+
+    /// Slashes part of a provider's bond for pushing a bad price
+    pub fn slash(&mut self, provider: AccountId, amount: U128) -> U128 {
+        self.assert_role(Role::Admin);
+        let slashed = self.slash_bond(&provider, amount.0);
+        self.slashed_funds += slashed;
+        U128(slashed)
+    }
+
+    /// Transfers slashed funds out of the contract
+    pub fn withdraw_slashed(&mut self, receiver_id: AccountId, amount: U128) -> Promise {
+        self.assert_owner();
+        assert!(
+            amount.0 <= self.slashed_funds,
+            "amount exceeds the slashed funds"
+        );
+        self.slashed_funds -= amount.0;
+        Promise::new(receiver_id).transfer(amount.0)
+    }
+
+    /// Returns the slashed funds held by the contract
+    pub fn get_slashed_funds(&self) -> U128 {
+        U128(self.slashed_funds)
+    }
+
+    /// Sets the time providers have to wait between unbonding and withdrawing
+    pub fn set_unbonding_delay(&mut self, delay: u64) {
+        self.assert_role(Role::Admin);
+        self.unbonding_delay = delay;
+    }
+
+    /// Returns the time providers have to wait between unbonding and withdrawing
+    pub fn get_unbonding_delay(&self) -> u64 {
+        self.unbonding_delay
+    }
+
+    /// Returns the bond and unbonding funds of a provider
+    pub fn get_bond(&self, provider: AccountId) -> Option<BondInfo> {
+        self.get_provider_option(&provider)
+            .map(|provider| BondInfo {
+                bond: U128(provider.bond),
+                unbonding: provider.unbonding,
+            })
+    }
+}
+
+/// Bonding tests
+#[cfg(test)]
+mod tests {
+
+    use aggregate::AggregateOptions;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+    use price_pair::STORAGE_COST;
+
+    use super::*;
+
+    fn alice() -> AccountId {
+        "alice.near".parse().unwrap()
+    }
+    fn bob() -> AccountId {
+        "bob.near".parse().unwrap()
+    }
+    fn carol() -> AccountId {
+        "carol.near".parse().unwrap()
+    }
+
+    fn get_context(
+        predecessor_account_id: AccountId,
+        current_account_id: AccountId,
+    ) -> VMContextBuilder {
+        let mut builder = VMContextBuilder::new();
+        builder
+            .current_account_id(current_account_id.clone())
+            .signer_account_id("robert.testnet".parse().unwrap())
+            .predecessor_account_id(predecessor_account_id.clone())
+            .attached_deposit(STORAGE_COST);
+        builder
+    }
+
+    /// Creates a contract owned by alice where bob bonded 100 yocto
+    fn setup() -> FPOContract {
+        testing_env!(get_context(alice(), alice()).build());
+        let mut fpo_contract = FPOContract::new();
+        fpo_contract.set_unbonding_delay(50);
+
+        testing_env!(get_context(bob(), alice())
+            .attached_deposit(100)
+            .block_timestamp(100)
+            .build());
+        fpo_contract.bond();
+        fpo_contract
+    }
+
+    #[test]
+    fn bond_and_unbond() {
+        let mut fpo_contract = setup();
+        assert_eq!(
+            Some(BondInfo {
+                bond: U128(100),
+                unbonding: None,
+            }),
+            fpo_contract.get_bond(bob())
+        );
+
+        fpo_contract.unbond(U128(30));
+        fpo_contract.unbond(U128(10));
+        assert_eq!(
+            Some(BondInfo {
+                bond: U128(60),
+                unbonding: Some(Unbonding {
+                    amount: U128(40),
+                    available_at: 150,
+                }),
+            }),
+            fpo_contract.get_bond(bob())
+        );
+
+        testing_env!(get_context(bob(), alice()).block_timestamp(150).build());
+        fpo_contract.withdraw_unbonded();
+        assert_eq!(None, fpo_contract.get_bond(bob()).unwrap().unbonding);
+    }
+
+    #[test]
+    #[should_panic(expected = "unbonding delay has not passed")]
+    fn withdraw_before_delay() {
+        let mut fpo_contract = setup();
+        fpo_contract.unbond(U128(30));
+        testing_env!(get_context(bob(), alice()).block_timestamp(149).build());
+        fpo_contract.withdraw_unbonded();
+    }
+
+    #[test]
+    #[should_panic(expected = "amount exceeds the bond")]
+    fn unbond_too_much() {
+        let mut fpo_contract = setup();
+        fpo_contract.unbond(U128(101));
+    }
+
+    #[test]
+    fn slash_bond_and_unbonding() {
+        let mut fpo_contract = setup();
+        fpo_contract.unbond(U128(30));
+
+        testing_env!(get_context(alice(), alice()).build());
+        assert_eq!(U128(50), fpo_contract.slash(bob(), U128(50)));
+        assert_eq!(U128(20), fpo_contract.get_bond(bob()).unwrap().bond);
+
+        assert_eq!(U128(50), fpo_contract.slash(bob(), U128(1000)));
+        assert_eq!(
+            Some(BondInfo {
+                bond: U128(0),
+                unbonding: None,
+            }),
+            fpo_contract.get_bond(bob())
+        );
+        assert_eq!(U128(100), fpo_contract.get_slashed_funds());
+
+        fpo_contract.withdraw_slashed(carol(), U128(100));
+        assert_eq!(U128(0), fpo_contract.get_slashed_funds());
+    }
+
+    #[test]
+    #[should_panic(expected = "missing role Admin")]
+    fn slash_not_admin() {
+        let mut fpo_contract = setup();
+        fpo_contract.slash(bob(), U128(50));
+    }
+
+    #[test]
+    fn aggregate_bond_weighted() {
+        let mut fpo_contract = setup();
+        testing_env!(get_context(bob(), alice()).build());
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(4000), None, None);
+
+        testing_env!(get_context(carol(), alice()).attached_deposit(300).build());
+        fpo_contract.bond();
+        testing_env!(get_context(carol(), alice()).build());
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2000), None, None);
+
+        let pairs = vec!["ETH/USD".to_string(); 2];
+        let options = Some(AggregateOptions {
+            bond_weighted: true,
+            ..Default::default()
+        });
+        assert_eq!(
+            Some(U128(2500)),
+            fpo_contract.aggregate_avg(pairs.clone(), vec![bob(), carol()], 0, options.clone())
+        );
+        assert_eq!(
+            Some(U128(2000)),
+            fpo_contract.aggregate_median(pairs.clone(), vec![bob(), carol()], 0, options)
+        );
+        assert_eq!(
+            Some(U128(3000)),
+            fpo_contract.aggregate_avg(pairs, vec![bob(), carol()], 0, None)
+        );
+    }
+}
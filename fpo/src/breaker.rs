@@ -0,0 +1,248 @@
+use crate::*;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::Timestamp;
+use price_pair::PriceEntry;
+
+#[derive(
+    BorshDeserialize, BorshSerialize, Deserialize, Serialize, Debug, Clone, Default, PartialEq,
+)]
+#[serde(default)]
+pub struct BreakerConfig {
+    pub min_price: Option<U128>,          // Updates below are rejected
+    pub max_price: Option<U128>,          // Updates above are rejected
+    pub max_deviation: Option<u16>,       // Maximum change of a single update in basis points
+    pub confirmation_window: Option<u64>, // Time in nanoseconds to confirm a deviating update, rejected if None
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct PendingPrice {
+    pub price: U128,
+    pub pushed_at: Timestamp,
+    pub pusher: AccountId,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct BreakerState {
+    pub config: Option<BreakerConfig>,
+    pub pending: Option<PendingPrice>, // Deviating update waiting for confirmation
+}
+
+/// Outcome of a price update
+pub enum PriceUpdate {
+    Accepted(PriceEntry),
+    Pending(PriceEntry), // Held back by the circuit breaker until confirmed
+}
+
+/// Circuit breaker methods (internal)
+impl BreakerConfig {
+    /// Panics if a price is outside of the configured bounds
+    pub fn assert_within_bounds(&self, price: U128) {
+        if let Some(min_price) = self.min_price {
+            assert!(price.0 >= min_price.0, "price is below the minimum");
+        }
+        if let Some(max_price) = self.max_price {
+            assert!(price.0 <= max_price.0, "price is above the maximum");
+        }
+    }
+
+    /// Checks if moving from one price to another exceeds the maximum deviation
+    pub fn exceeds_deviation(&self, from: U128, to: U128) -> bool {
+        match self.max_deviation {
+            Some(max_deviation) => math::deviation_bps(from.0, to.0) > u128::from(max_deviation),
+            None => false,
+        }
+    }
+
+    /// Checks if a price confirms a pending update within the confirmation window
+    pub fn confirms(&self, pending: &PendingPrice, price: U128, now: Timestamp) -> bool {
+        match self.confirmation_window {
+            Some(window) => {
+                now <= pending.pushed_at.saturating_add(window)
+                    && !self.exceeds_deviation(pending.price, price)
+            }
+            None => false,
+        }
+    }
+}
+
+/// Public contract methods
+#[near_bindgen]
+impl FPOContract {
+    /// Sets or clears the circuit breaker of a price pair by a provider
+    pub fn set_breaker(&mut self, pair: String, config: Option<BreakerConfig>) {
+        let mut provider = self.get_provider_expect(&env::predecessor_account_id());
+        let pair_name = format!("{}:{}", pair, env::predecessor_account_id());
+        let mut entry = provider.get_entry_expect(&pair_name);
+        entry.breaker = config;
+        entry.pending = None;
+        provider.set_entry(&pair_name, entry);
+        self.set_provider(&env::predecessor_account_id(), provider);
+    }
+
+    /// Returns the circuit breaker configuration and pending update of a price pair by a provider
+    pub fn get_breaker_state(&self, pair: String, provider: AccountId) -> Option<BreakerState> {
+        let pair_name = format!("{}:{}", pair, provider);
+        self.get_provider_option(&provider)
+            .and_then(|provider| provider.get_entry_option(&pair_name))
+            .map(|entry| BreakerState {
+                config: entry.breaker,
+                pending: entry.pending,
+            })
+    }
+}
+
+/// Circuit breaker tests
+#[cfg(test)]
+mod tests {
+
+    use near_sdk::test_utils::{get_logs, VMContextBuilder};
+    use near_sdk::testing_env;
+    use price_pair::STORAGE_COST;
+
+    use super::*;
+
+    fn alice() -> AccountId {
+        "alice.near".parse().unwrap()
+    }
+
+    fn get_context(
+        predecessor_account_id: AccountId,
+        current_account_id: AccountId,
+    ) -> VMContextBuilder {
+        let mut builder = VMContextBuilder::new();
+        builder
+            .current_account_id(current_account_id.clone())
+            .signer_account_id("robert.testnet".parse().unwrap())
+            .predecessor_account_id(predecessor_account_id.clone())
+            .attached_deposit(STORAGE_COST);
+        builder
+    }
+
+    /// Creates a contract where alice provides ETH/USD at 2500 with a breaker
+    fn setup(confirmation_window: Option<u64>) -> FPOContract {
+        testing_env!(get_context(alice(), alice()).block_timestamp(100).build());
+        let mut fpo_contract = FPOContract::new();
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2500), None, None);
+        fpo_contract.set_breaker(
+            "ETH/USD".to_string(),
+            Some(BreakerConfig {
+                min_price: Some(U128(100)),
+                max_price: Some(U128(100_000)),
+                max_deviation: Some(1000),
+                confirmation_window,
+            }),
+        );
+        fpo_contract
+    }
+
+    #[test]
+    fn push_within_deviation() {
+        let mut fpo_contract = setup(None);
+        fpo_contract.push_data("ETH/USD".to_string(), U128(2750), None);
+        assert_eq!(
+            Some(U128(2750)),
+            fpo_contract.get_price("ETH/USD".to_string(), &alice())
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "price is below the minimum")]
+    fn push_below_minimum() {
+        let mut fpo_contract = setup(Some(60));
+        fpo_contract.push_data("ETH/USD".to_string(), U128(0), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "price is above the maximum")]
+    fn push_above_maximum() {
+        let mut fpo_contract = setup(Some(60));
+        fpo_contract.push_data("ETH/USD".to_string(), U128(1_000_000), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "price deviates too much from the last price")]
+    fn push_deviating_without_window() {
+        let mut fpo_contract = setup(None);
+        fpo_contract.push_data("ETH/USD".to_string(), U128(2751), None);
+    }
+
+    #[test]
+    fn confirm_deviating_push() {
+        let mut fpo_contract = setup(Some(60));
+        fpo_contract.push_data("ETH/USD".to_string(), U128(5000), None);
+        assert_eq!(
+            Some(U128(2500)),
+            fpo_contract.get_price("ETH/USD".to_string(), &alice())
+        );
+        assert_eq!(
+            Some(PendingPrice {
+                price: U128(5000),
+                pushed_at: 100,
+                pusher: alice(),
+            }),
+            fpo_contract
+                .get_breaker_state("ETH/USD".to_string(), alice())
+                .unwrap()
+                .pending
+        );
+        assert_eq!(
+            get_logs().last().unwrap(),
+            r#"EVENT_JSON:{"standard":"fpo","version":"1.0.0","event":"circuit_breaker_tripped","data":[{"pair":"ETH/USD","provider":"alice.near","price":"2500","pending_price":"5000","timestamp":100,"pusher":"alice.near"}]}"#
+        );
+
+        testing_env!(get_context(alice(), alice()).block_timestamp(150).build());
+        fpo_contract.push_data("ETH/USD".to_string(), U128(5100), None);
+        assert_eq!(
+            Some(U128(5100)),
+            fpo_contract.get_price("ETH/USD".to_string(), &alice())
+        );
+        assert_eq!(
+            None,
+            fpo_contract
+                .get_breaker_state("ETH/USD".to_string(), alice())
+                .unwrap()
+                .pending
+        );
+    }
+
+    #[test]
+    fn confirm_after_window() {
+        let mut fpo_contract = setup(Some(60));
+        fpo_contract.push_data("ETH/USD".to_string(), U128(5000), None);
+
+        // too late, the second push starts a new confirmation window
+        testing_env!(get_context(alice(), alice()).block_timestamp(161).build());
+        fpo_contract.push_data("ETH/USD".to_string(), U128(5000), None);
+        assert_eq!(
+            Some(U128(2500)),
+            fpo_contract.get_price("ETH/USD".to_string(), &alice())
+        );
+        assert_eq!(
+            161,
+            fpo_contract
+                .get_breaker_state("ETH/USD".to_string(), alice())
+                .unwrap()
+                .pending
+                .unwrap()
+                .pushed_at
+        );
+    }
+
+    #[test]
+    fn clear_breaker() {
+        let mut fpo_contract = setup(None);
+        fpo_contract.set_breaker("ETH/USD".to_string(), None);
+        fpo_contract.push_data("ETH/USD".to_string(), U128(0), None);
+        assert_eq!(
+            Some(U128(0)),
+            fpo_contract.get_price("ETH/USD".to_string(), &alice())
+        );
+        assert_eq!(
+            Some(BreakerState {
+                config: None,
+                pending: None,
+            }),
+            fpo_contract.get_breaker_state("ETH/USD".to_string(), alice())
+        );
+    }
+}
@@ -1,15 +1,23 @@
 #![allow(clippy::too_many_arguments)]
 
 use crate::*;
+use aggregate::AggregateOptions;
+use events::{FPOEvent, ForwardResolvedData, PriceForwardedData};
 use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::serde_json;
 use near_sdk::Timestamp;
-use near_sdk::{ext_contract, log, Balance, Gas, Promise};
+use near_sdk::{ext_contract, Balance, Gas, Promise, PromiseResult};
+use price_pair::PriceEntry;
 use std::convert::TryInto;
 // use near_account_id::AccountId;
-const GAS_TO_SEND_PRICE: Gas = Gas(5_000_000_000_000); // Todo: calculate and optimize
+const GAS_TO_SEND_PRICE: Gas = Gas(5_000_000_000_000); // default gas for the callback, per pair in get_prices_call()
+const GAS_RESERVE: Gas = Gas(5_000_000_000_000); // gas kept to finish the call after creating the callback
+const GAS_FOR_RESOLVE_FORWARD: Gas = Gas(5_000_000_000_000); // gas for on_forward_resolved()
 const ZERO_BALANCE: Balance = 0;
+const DEFAULT_CALLBACK_METHOD: &str = "on_price_received";
+const DEFAULT_ENTRIES_CALLBACK_METHOD: &str = "on_entries_received"; // default for get_entries_call()
 
-#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Debug)]
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub enum PriceType {
     Single,
     Multiple,
@@ -18,144 +26,361 @@ pub enum PriceType {
     Collect, // same as multiple but with min_last_update
     MeanMany,
     MedianMany,
+    Derived,
+    Entries, // full price entries instead of prices
 }
 
-/// Price consumer trait for consumer contract
-#[ext_contract(ext_price_consumer)]
-pub trait PriceConsumer {
-    fn on_price_received(
+/// Results that can be forwarded to a price consumer, logged by their price
+pub trait ForwardedResult: Serialize {
+    fn price(&self) -> Option<U128>;
+}
+
+impl ForwardedResult for Option<U128> {
+    fn price(&self) -> Option<U128> {
+        *self
+    }
+}
+
+impl ForwardedResult for Option<PriceEntry> {
+    fn price(&self) -> Option<U128> {
+        self.as_ref().map(|entry| entry.price)
+    }
+}
+
+/// Arguments of the price consumer's callback, `on_price_received` (`on_entries_received` for
+/// entries) unless the caller names another method. `msg` is passed back unchanged and left out
+/// if not given
+#[derive(Serialize)]
+struct PriceConsumerArgs<'a, T> {
+    sender_id: &'a AccountId,
+    request_id: u64, // Increases with every forward, so also with every forward requested by `sender_id`
+    pairs: &'a [String],
+    providers: &'a [AccountId],
+    price_type: &'a PriceType,
+    results: &'a [T],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    msg: Option<String>,
+}
+
+/// Callbacks of this contract
+#[ext_contract(ext_self)]
+pub trait FPOResolver {
+    fn on_forward_resolved(
         &self,
         sender_id: AccountId,
+        request_id: u64,
+        receiver_id: AccountId,
+        price_type: PriceType,
+        fee: U128,
+    ) -> bool;
+}
+
+/// Private contract methods
+impl FPOContract {
+    /// Returns the gas to attach to the callback, panics if the prepaid gas can't cover it
+    fn callback_gas(&self, requested: Option<Gas>, default: Gas) -> Gas {
+        let gas = requested.unwrap_or(default);
+        let available = env::prepaid_gas() - env::used_gas();
+        let reserved = GAS_RESERVE + GAS_FOR_RESOLVE_FORWARD;
+        assert!(
+            gas.0.saturating_add(reserved.0) <= available.0,
+            "not enough gas attached, the callback needs {} and {} are reserved but only {} are left",
+            gas.0,
+            reserved.0,
+            available.0
+        );
+        gas
+    }
+
+    /// Emits a `price_forwarded` event and forwards the results to `method` of the price consumer,
+    /// followed by `on_forward_resolved()` to check the delivery
+    fn forward_prices<T: ForwardedResult>(
+        &mut self,
         pairs: Vec<String>,
         providers: Vec<AccountId>,
         price_type: PriceType,
-        results: Vec<Option<U128>>,
-    );
+        results: Vec<T>,
+        receiver_id: AccountId,
+        gas: Gas,
+        method: Option<String>,
+        msg: Option<String>,
+    ) -> Promise {
+        let sender_id = env::predecessor_account_id();
+        self.request_nonce += 1;
+        let request_id = self.request_nonce;
+
+        FPOEvent::PriceForwarded(vec![PriceForwardedData {
+            sender_id: &sender_id,
+            request_id,
+            receiver_id: &receiver_id,
+            price_type: &price_type,
+            pairs: &pairs,
+            providers: &providers,
+            results: &results
+                .iter()
+                .map(ForwardedResult::price)
+                .collect::<Vec<_>>(),
+        }])
+        .emit();
+
+        let args = serde_json::to_vec(&PriceConsumerArgs {
+            sender_id: &sender_id,
+            request_id,
+            pairs: &pairs,
+            providers: &providers,
+            price_type: &price_type,
+            results: &results,
+            msg,
+        })
+        .expect("failed to serialize callback arguments");
+        Promise::new(receiver_id.clone())
+            .function_call(
+                method.unwrap_or_else(|| DEFAULT_CALLBACK_METHOD.to_string()),
+                args,
+                ZERO_BALANCE,
+                gas,
+            )
+            .then(ext_self::on_forward_resolved(
+                sender_id,
+                request_id,
+                receiver_id,
+                price_type,
+                U128(ZERO_BALANCE), // forwarding is free until query fees are charged
+                env::current_account_id(),
+                ZERO_BALANCE,
+                GAS_FOR_RESOLVE_FORWARD,
+            ))
+    }
 }
 
 /// Public contract methods
 #[near_bindgen]
 impl FPOContract {
+    /// Returns the id of the last forward
+    pub fn get_request_nonce(&self) -> u64 {
+        self.request_nonce
+    }
+
+    /// Emits a `forward_resolved` event with the outcome of a forward and refunds the fee
+    /// to the sender if the price consumer failed
+    #[private]
+    pub fn on_forward_resolved(
+        &self,
+        sender_id: AccountId,
+        request_id: u64,
+        receiver_id: AccountId,
+        price_type: PriceType,
+        fee: U128,
+    ) -> bool {
+        let delivered = matches!(env::promise_result(0), PromiseResult::Successful(_));
+        let refund = if delivered { 0 } else { fee.0 };
+        if refund > 0 {
+            Promise::new(sender_id.clone()).transfer(refund);
+        }
+
+        FPOEvent::ForwardResolved(vec![ForwardResolvedData {
+            sender_id: &sender_id,
+            request_id,
+            receiver_id: &receiver_id,
+            price_type: &price_type,
+            delivered,
+            refund: U128(refund),
+        }])
+        .emit();
+        delivered
+    }
+
     /// Forwards a price to the price consumer
     pub fn get_price_call(
-        &self,
+        &mut self,
         pair: String,
         provider: AccountId,
         receiver_id: AccountId,
+        callback_gas: Option<Gas>,
+        method: Option<String>,
+        msg: Option<String>,
     ) -> Promise {
-        let sender_id = env::predecessor_account_id();
         let price = self.get_price(pair.clone(), &provider);
-        ext_price_consumer::on_price_received(
-            sender_id,
+        self.forward_prices(
             vec![pair],
             vec![provider],
             PriceType::Single,
             vec![price],
             receiver_id,
-            ZERO_BALANCE,
-            GAS_TO_SEND_PRICE,
+            self.callback_gas(callback_gas, GAS_TO_SEND_PRICE),
+            method,
+            msg,
+        )
+    }
+
+    /// Forwards a derived price to the price consumer, reported with this contract as provider
+    pub fn get_derived_price_call(
+        &mut self,
+        name: String,
+        receiver_id: AccountId,
+        callback_gas: Option<Gas>,
+        method: Option<String>,
+        msg: Option<String>,
+    ) -> Promise {
+        let price = self.get_derived_price(name.clone());
+        self.forward_prices(
+            vec![name],
+            vec![env::current_account_id()],
+            PriceType::Derived,
+            vec![price],
+            receiver_id,
+            self.callback_gas(callback_gas, GAS_TO_SEND_PRICE),
+            method,
+            msg,
         )
     }
 
     /// Forwards prices to the price consumer
     pub fn get_prices_call(
-        &self,
+        &mut self,
         pairs: Vec<String>,
         providers: Vec<AccountId>,
         receiver_id: AccountId,
+        callback_gas: Option<Gas>,
+        method: Option<String>,
+        msg: Option<String>,
     ) -> Promise {
-        let sender_id = env::predecessor_account_id();
         let entries = self.get_prices(pairs.clone(), providers.clone());
-        log!("entries: {:?}", entries);
         let num_pairs = pairs.len();
-        ext_price_consumer::on_price_received(
-            sender_id,
+        self.forward_prices(
             pairs,
             providers,
             PriceType::Multiple,
             entries,
             receiver_id,
-            ZERO_BALANCE,
-            GAS_TO_SEND_PRICE * num_pairs.try_into().unwrap(),
+            self.callback_gas(
+                callback_gas,
+                GAS_TO_SEND_PRICE * num_pairs.try_into().unwrap(),
+            ),
+            method,
+            msg,
+        )
+    }
+
+    /// Forwards the result of get_entries() to the price consumer, by default to its
+    /// `on_entries_received` method
+    pub fn get_entries_call(
+        &mut self,
+        pairs: Vec<String>,
+        providers: Vec<AccountId>,
+        receiver_id: AccountId,
+        callback_gas: Option<Gas>,
+        method: Option<String>,
+        msg: Option<String>,
+    ) -> Promise {
+        let entries = self.get_entries(pairs.clone(), providers.clone());
+        let num_pairs = pairs.len();
+        self.forward_prices(
+            pairs,
+            providers,
+            PriceType::Entries,
+            entries,
+            receiver_id,
+            self.callback_gas(
+                callback_gas,
+                GAS_TO_SEND_PRICE * num_pairs.try_into().unwrap(),
+            ),
+            Some(method.unwrap_or_else(|| DEFAULT_ENTRIES_CALLBACK_METHOD.to_string())),
+            msg,
         )
     }
 
     /// Forwards the result of aggregate_avg() to the price consumer
     pub fn aggregate_avg_call(
-        &self,
+        &mut self,
         pairs: Vec<String>,
         providers: Vec<AccountId>,
         min_last_update: Timestamp,
         receiver_id: AccountId,
+        options: Option<AggregateOptions>,
+        callback_gas: Option<Gas>,
+        method: Option<String>,
+        msg: Option<String>,
     ) -> Promise {
-        let sender_id = env::predecessor_account_id();
-        let avg = self.aggregate_avg(pairs.clone(), providers.clone(), min_last_update);
-        ext_price_consumer::on_price_received(
-            sender_id,
+        let avg = self.aggregate_avg(pairs.clone(), providers.clone(), min_last_update, options);
+        self.forward_prices(
             pairs,
             providers,
             PriceType::Mean,
             vec![avg],
             receiver_id,
-            ZERO_BALANCE,
-            GAS_TO_SEND_PRICE,
+            self.callback_gas(callback_gas, GAS_TO_SEND_PRICE),
+            method,
+            msg,
         )
     }
 
     /// Forwards the result of aggregate_median() to the price consumer
     pub fn aggregate_median_call(
-        &self,
+        &mut self,
         pairs: Vec<String>,
         providers: Vec<AccountId>,
         min_last_update: Timestamp,
         receiver_id: AccountId,
+        options: Option<AggregateOptions>,
+        callback_gas: Option<Gas>,
+        method: Option<String>,
+        msg: Option<String>,
     ) -> Promise {
-        let sender_id = env::predecessor_account_id();
-        let median = self.aggregate_median(pairs.clone(), providers.clone(), min_last_update);
-        ext_price_consumer::on_price_received(
-            sender_id,
+        let median =
+            self.aggregate_median(pairs.clone(), providers.clone(), min_last_update, options);
+        self.forward_prices(
             pairs,
             providers,
             PriceType::Median,
             vec![median],
             receiver_id,
-            ZERO_BALANCE,
-            GAS_TO_SEND_PRICE,
+            self.callback_gas(callback_gas, GAS_TO_SEND_PRICE),
+            method,
+            msg,
         )
     }
 
     /// Forwards the result of aggregate_collect() to the price consumer
     pub fn aggregate_collect_call(
-        &self,
+        &mut self,
         pairs: Vec<String>,
         providers: Vec<AccountId>,
         min_last_update: Timestamp,
         receiver_id: AccountId,
+        options: Option<AggregateOptions>,
+        callback_gas: Option<Gas>,
+        method: Option<String>,
+        msg: Option<String>,
     ) -> Promise {
-        let sender_id = env::predecessor_account_id();
-        let collect = self.aggregate_collect(pairs.clone(), providers.clone(), min_last_update);
-        ext_price_consumer::on_price_received(
-            sender_id,
+        let collect =
+            self.aggregate_collect(pairs.clone(), providers.clone(), min_last_update, options);
+        self.forward_prices(
             pairs,
             providers,
             PriceType::Collect,
             collect,
             receiver_id,
-            ZERO_BALANCE,
-            GAS_TO_SEND_PRICE,
+            self.callback_gas(callback_gas, GAS_TO_SEND_PRICE),
+            method,
+            msg,
         )
     }
 
     /// Forwards the result of aggregate_avg_many() to the price consumer
     pub fn aggregate_avg_many_call(
-        &self,
+        &mut self,
         pairs: Vec<Vec<String>>,
         providers: Vec<Vec<AccountId>>,
         min_last_update: Timestamp,
         receiver_id: AccountId,
+        options: Option<AggregateOptions>,
+        callback_gas: Option<Gas>,
+        method: Option<String>,
+        msg: Option<String>,
     ) -> Promise {
-        let sender_id = env::predecessor_account_id();
-        let avgs = self.aggregate_avg_many(pairs.clone(), providers.clone(), min_last_update);
+        let avgs =
+            self.aggregate_avg_many(pairs.clone(), providers.clone(), min_last_update, options);
 
         // get the first element of every subarray in `pairs`
         let pairs = pairs
@@ -163,28 +388,32 @@ impl FPOContract {
             .map(|p| p.first().unwrap().clone())
             .collect::<Vec<String>>();
 
-        ext_price_consumer::on_price_received(
-            sender_id,
+        self.forward_prices(
             pairs,
             vec![], // exclude providers
             PriceType::MeanMany,
             avgs,
             receiver_id,
-            ZERO_BALANCE,
-            GAS_TO_SEND_PRICE,
+            self.callback_gas(callback_gas, GAS_TO_SEND_PRICE),
+            method,
+            msg,
         )
     }
 
     /// Forwards the result of aggregate_median_many() to the price consumer
     pub fn aggregate_median_many_call(
-        &self,
+        &mut self,
         pairs: Vec<Vec<String>>,
         providers: Vec<Vec<AccountId>>,
         min_last_update: Timestamp,
         receiver_id: AccountId,
+        options: Option<AggregateOptions>,
+        callback_gas: Option<Gas>,
+        method: Option<String>,
+        msg: Option<String>,
     ) -> Promise {
-        let sender_id = env::predecessor_account_id();
-        let medians = self.aggregate_avg_many(pairs.clone(), providers.clone(), min_last_update);
+        let medians =
+            self.aggregate_avg_many(pairs.clone(), providers.clone(), min_last_update, options);
 
         // get the first element of every subarray in `pairs` to submit as associated pair name
         let pairs = pairs
@@ -192,15 +421,220 @@ impl FPOContract {
             .map(|p| p.first().unwrap().clone())
             .collect::<Vec<String>>();
 
-        ext_price_consumer::on_price_received(
-            sender_id,
+        self.forward_prices(
             pairs,
             vec![], // exclude providers
             PriceType::MedianMany,
             medians,
             receiver_id,
-            ZERO_BALANCE,
-            GAS_TO_SEND_PRICE,
+            self.callback_gas(callback_gas, GAS_TO_SEND_PRICE),
+            method,
+            msg,
         )
     }
 }
+
+/// Price forwarding tests
+#[cfg(test)]
+mod tests {
+
+    use near_sdk::mock::VmAction;
+    use near_sdk::test_utils::{get_created_receipts, get_logs, VMContextBuilder};
+    use near_sdk::testing_env;
+    use near_sdk::{RuntimeFeesConfig, VMConfig};
+    use price_pair::STORAGE_COST;
+
+    use super::*;
+
+    fn alice() -> AccountId {
+        "alice.near".parse().unwrap()
+    }
+    fn consumer() -> AccountId {
+        "consumer.near".parse().unwrap()
+    }
+
+    fn get_context(
+        predecessor_account_id: AccountId,
+        current_account_id: AccountId,
+    ) -> VMContextBuilder {
+        let mut builder = VMContextBuilder::new();
+        builder
+            .current_account_id(current_account_id.clone())
+            .signer_account_id("robert.testnet".parse().unwrap())
+            .predecessor_account_id(predecessor_account_id.clone())
+            .attached_deposit(STORAGE_COST);
+        builder
+    }
+
+    fn setup(prepaid_gas: Gas) -> FPOContract {
+        testing_env!(get_context(alice(), alice()).build());
+        let mut fpo_contract = FPOContract::new();
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2000), None, None);
+        fpo_contract.create_pair("BTC/USD".to_string(), 8, U128(30000), None, None);
+        testing_env!(get_context(alice(), alice())
+            .prepaid_gas(prepaid_gas)
+            .build());
+        fpo_contract
+    }
+
+    #[test]
+    fn forward_with_callback_gas() {
+        let mut fpo_contract = setup(Gas(300_000_000_000_000));
+        fpo_contract.get_price_call(
+            "ETH/USD".to_string(),
+            alice(),
+            consumer(),
+            Some(Gas(200_000_000_000_000)),
+            None,
+            None,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "not enough gas attached")]
+    fn forward_with_too_much_callback_gas() {
+        let mut fpo_contract = setup(Gas(300_000_000_000_000));
+        fpo_contract.get_price_call(
+            "ETH/USD".to_string(),
+            alice(),
+            consumer(),
+            Some(Gas(296_000_000_000_000)),
+            None,
+            None,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "not enough gas attached")]
+    fn forward_many_pairs_with_little_gas() {
+        // the default callback gas grows with the number of pairs
+        let mut fpo_contract = setup(Gas(14_000_000_000_000));
+        fpo_contract.get_prices_call(
+            vec!["ETH/USD".to_string(), "BTC/USD".to_string()],
+            vec![alice(), alice()],
+            consumer(),
+            None,
+            None,
+            None,
+        );
+    }
+
+    fn resolve(fpo_contract: &FPOContract, result: PromiseResult, fee: u128) -> bool {
+        testing_env!(
+            get_context(alice(), alice()).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![result],
+        );
+        fpo_contract.on_forward_resolved(alice(), 1, consumer(), PriceType::Single, U128(fee))
+    }
+
+    #[test]
+    fn forward_delivered() {
+        let fpo_contract = setup(Gas(300_000_000_000_000));
+        assert!(resolve(
+            &fpo_contract,
+            PromiseResult::Successful(vec![]),
+            100
+        ));
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"fpo","version":"1.0.0","event":"forward_resolved","data":[{"sender_id":"alice.near","request_id":1,"receiver_id":"consumer.near","price_type":"Single","delivered":true,"refund":"0"}]}"#
+            ]
+        );
+    }
+
+    #[test]
+    fn forward_failed() {
+        let fpo_contract = setup(Gas(300_000_000_000_000));
+        assert!(!resolve(&fpo_contract, PromiseResult::Failed, 100));
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"fpo","version":"1.0.0","event":"forward_resolved","data":[{"sender_id":"alice.near","request_id":1,"receiver_id":"consumer.near","price_type":"Single","delivered":false,"refund":"100"}]}"#
+            ]
+        );
+    }
+
+    #[test]
+    fn serialize_consumer_args() {
+        let args = PriceConsumerArgs {
+            sender_id: &alice(),
+            request_id: 1,
+            pairs: &["ETH/USD".to_string()],
+            providers: &[alice()],
+            price_type: &PriceType::Single,
+            results: &[Some(U128(2000))],
+            msg: None,
+        };
+        assert_eq!(
+            r#"{"sender_id":"alice.near","request_id":1,"pairs":["ETH/USD"],"providers":["alice.near"],"price_type":"Single","results":["2000"]}"#,
+            serde_json::to_string(&args).unwrap()
+        );
+
+        let args = PriceConsumerArgs {
+            msg: Some("liquidation:42".to_string()),
+            ..args
+        };
+        assert_eq!(
+            r#"{"sender_id":"alice.near","request_id":1,"pairs":["ETH/USD"],"providers":["alice.near"],"price_type":"Single","results":["2000"],"msg":"liquidation:42"}"#,
+            serde_json::to_string(&args).unwrap()
+        );
+    }
+
+    #[test]
+    fn request_ids_increase_across_callers() {
+        let mut fpo_contract = setup(Gas(300_000_000_000_000));
+        let storage_used_before = env::storage_usage();
+        for _ in 0..2 {
+            fpo_contract.get_price_call(
+                "ETH/USD".to_string(),
+                alice(),
+                consumer(),
+                None,
+                None,
+                None,
+            );
+        }
+        // forwarding doesn't store anything per caller
+        assert_eq!(storage_used_before, env::storage_usage());
+        assert_eq!(2, fpo_contract.get_request_nonce());
+        assert_eq!(
+            get_logs()[1],
+            r#"EVENT_JSON:{"standard":"fpo","version":"1.0.0","event":"price_forwarded","data":[{"sender_id":"alice.near","request_id":2,"receiver_id":"consumer.near","price_type":"Single","pairs":["ETH/USD"],"providers":["alice.near"],"results":["2000"]}]}"#
+        );
+
+        testing_env!(get_context(consumer(), alice())
+            .prepaid_gas(Gas(300_000_000_000_000))
+            .build());
+        fpo_contract.get_price_call("ETH/USD".to_string(), alice(), consumer(), None, None, None);
+        assert_eq!(3, fpo_contract.get_request_nonce());
+        assert_eq!(
+            get_logs()[0],
+            r#"EVENT_JSON:{"standard":"fpo","version":"1.0.0","event":"price_forwarded","data":[{"sender_id":"consumer.near","request_id":3,"receiver_id":"consumer.near","price_type":"Single","pairs":["ETH/USD"],"providers":["alice.near"],"results":["2000"]}]}"#
+        );
+    }
+
+    #[test]
+    fn forward_entries() {
+        let mut fpo_contract = setup(Gas(300_000_000_000_000));
+        fpo_contract.get_entries_call(
+            vec!["ETH/USD".to_string(), "ETH/USD".to_string()],
+            vec![alice(), consumer()],
+            consumer(),
+            None,
+            None,
+            None,
+        );
+        assert_eq!(
+            get_logs()[0],
+            r#"EVENT_JSON:{"standard":"fpo","version":"1.0.0","event":"price_forwarded","data":[{"sender_id":"alice.near","request_id":1,"receiver_id":"consumer.near","price_type":"Entries","pairs":["ETH/USD","ETH/USD"],"providers":["alice.near","consumer.near"],"results":["2000",null]}]}"#
+        );
+        assert!(matches!(
+            &get_created_receipts()[0].actions[0],
+            VmAction::FunctionCall { function_name, .. } if function_name == "on_entries_received"
+        ));
+    }
+}
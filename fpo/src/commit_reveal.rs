@@ -0,0 +1,444 @@
+use crate::*;
+use near_sdk::json_types::Base64VecU8;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::Timestamp;
+use price_pair::PriceEntry;
+
+/// Lengths of the phases of a commit-reveal round. Rounds are aligned to the block
+/// timestamp, so providers using the same lengths commit and reveal at the same time
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct CommitRevealConfig {
+    pub commit_window: u64, // Nanoseconds at the start of a round in which hashes can be committed
+    pub reveal_window: u64, // Nanoseconds after the commit window in which prices can be revealed
+}
+
+#[derive(
+    BorshDeserialize, BorshSerialize, Deserialize, Serialize, Debug, Clone, Copy, PartialEq,
+)]
+pub enum RoundPhase {
+    Commit,
+    Reveal,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Commitment {
+    pub hash: Base64VecU8, // sha256 of "{pair}:{provider}:{round}:{price}:{salt}"
+    pub round: u64,
+    pub pusher: AccountId,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct CommitReveal {
+    pub config: CommitRevealConfig,
+    pub commitment: Option<Commitment>, // Latest hash that hasn't been revealed
+    pub revealed_round: Option<u64>,    // Round in which the current price was revealed
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct RoundState {
+    pub round: u64,
+    pub phase: RoundPhase,
+    pub phase_ends_at: Timestamp,
+    pub commitment: Option<Commitment>, // Commitment for this round, if any
+    pub revealed_round: Option<u64>,
+}
+
+/// Commit-reveal round methods (internal)
+impl CommitRevealConfig {
+    fn round_length(&self) -> u64 {
+        self.commit_window + self.reveal_window
+    }
+
+    /// Returns the round at a given time
+    pub fn round_at(&self, now: Timestamp) -> u64 {
+        now / self.round_length()
+    }
+
+    /// Returns the phase of the round at a given time and when it ends
+    pub fn phase_at(&self, now: Timestamp) -> (RoundPhase, Timestamp) {
+        let round_start = self.round_at(now) * self.round_length();
+        if now - round_start < self.commit_window {
+            (RoundPhase::Commit, round_start + self.commit_window)
+        } else {
+            (RoundPhase::Reveal, round_start + self.round_length())
+        }
+    }
+}
+
+/// Returns the hash providers commit to for a price and salt, bound to the pair, provider
+/// and round so a commitment can't be copied by another provider or replayed in another round
+pub fn commitment_hash(
+    pair: &str,
+    provider: &AccountId,
+    round: u64,
+    price: U128,
+    salt: &str,
+) -> Vec<u8> {
+    env::sha256(format!("{}:{}:{}:{}:{}", pair, provider, round, price.0, salt).as_bytes())
+}
+
+/// Commit-reveal methods (internal)
+impl Provider {
+    /// Panics if a pair only accepts prices through `commit_price()` and `reveal_price()`
+    pub fn assert_no_commit_reveal(&self, pair_name: &str) {
+        assert!(
+            self.get_entry_option(pair_name)
+                .map(|entry| entry.commit_reveal.is_none())
+                .unwrap_or(true),
+            "pair uses commit-reveal, use commit_price and reveal_price"
+        );
+    }
+}
+
+/// Private contract methods
+impl FPOContract {
+    /// Returns the provider and commit-reveal state of a pair, panics if the pair doesn't use commit-reveal
+    fn get_commit_reveal(
+        &self,
+        pair: &str,
+        provider_id: &AccountId,
+        pusher: &AccountId,
+    ) -> (Provider, PriceEntry, CommitReveal) {
+        let provider = self.get_pushing_provider(pair, provider_id, pusher);
+        let entry = provider.get_entry_expect(&format!("{}:{}", pair, provider_id));
+        let commit_reveal = entry
+            .commit_reveal
+            .clone()
+            .expect("pair does not use commit-reveal");
+        (provider, entry, commit_reveal)
+    }
+}
+
+/// Public contract methods
+#[near_bindgen]
+impl FPOContract {
+    /// Enables commit-reveal for a price pair by a provider, or disables it with None
+    pub fn set_commit_reveal(&mut self, pair: String, config: Option<CommitRevealConfig>) {
+        if let Some(config) = &config {
+            assert!(
+                config.commit_window > 0 && config.reveal_window > 0,
+                "commit and reveal windows must not be empty"
+            );
+        }
+        let mut provider = self.get_provider_expect(&env::predecessor_account_id());
+        let pair_name = format!("{}:{}", pair, env::predecessor_account_id());
+        let mut entry = provider.get_entry_expect(&pair_name);
+        entry.commit_reveal = config.map(|config| CommitReveal {
+            config,
+            commitment: None,
+            revealed_round: None,
+        });
+        provider.set_entry(&pair_name, entry);
+        self.set_provider(&env::predecessor_account_id(), provider);
+    }
+
+    /// Commits to a price for the current round by a provider, or by a pusher on behalf of
+    /// `provider`. `hash` is the sha256 of "{pair}:{provider}:{round}:{price}:{salt}"
+    pub fn commit_price(&mut self, pair: String, hash: Base64VecU8, provider: Option<AccountId>) {
+        self.assert_not_paused();
+        let pusher = env::predecessor_account_id();
+        let provider_id = provider.unwrap_or_else(|| pusher.clone());
+        let (mut provider, mut entry, mut commit_reveal) =
+            self.get_commit_reveal(&pair, &provider_id, &pusher);
+
+        let now = env::block_timestamp();
+        assert_eq!(
+            commit_reveal.config.phase_at(now).0,
+            RoundPhase::Commit,
+            "commit phase has ended"
+        );
+        commit_reveal.commitment = Some(Commitment {
+            hash,
+            round: commit_reveal.config.round_at(now),
+            pusher,
+        });
+        entry.commit_reveal = Some(commit_reveal);
+
+        provider.set_entry(&format!("{}:{}", pair, provider_id), entry);
+        self.set_provider(&provider_id, provider);
+    }
+
+    /// Reveals the price committed in the current round, which updates the price pair
+    pub fn reveal_price(
+        &mut self,
+        pair: String,
+        price: U128,
+        salt: String,
+        provider: Option<AccountId>,
+    ) {
+        self.assert_not_paused();
+        let pusher = env::predecessor_account_id();
+        let provider_id = provider.unwrap_or_else(|| pusher.clone());
+        let (mut provider, mut entry, mut commit_reveal) =
+            self.get_commit_reveal(&pair, &provider_id, &pusher);
+
+        let now = env::block_timestamp();
+        let round = commit_reveal.config.round_at(now);
+        let commitment = commit_reveal
+            .commitment
+            .take()
+            .expect("no commitment for this round");
+        assert!(commitment.round >= round, "reveal window has closed");
+        assert_eq!(
+            commit_reveal.config.phase_at(now).0,
+            RoundPhase::Reveal,
+            "reveal phase has not started"
+        );
+        assert_eq!(
+            commitment.hash.0,
+            commitment_hash(&pair, &provider_id, commitment.round, price, &salt),
+            "price does not match the commitment"
+        );
+        commit_reveal.revealed_round = Some(round);
+        entry.commit_reveal = Some(commit_reveal);
+
+        provider.set_entry(&format!("{}:{}", pair, provider_id), entry);
+        self.update_price(provider, &pair, price, &provider_id, &pusher);
+    }
+
+    /// Returns the current commit-reveal round of a price pair by a provider
+    pub fn get_round_state(&self, pair: String, provider: AccountId) -> Option<RoundState> {
+        let pair_name = format!("{}:{}", pair, provider);
+        let commit_reveal = self
+            .get_provider_option(&provider)
+            .and_then(|provider| provider.get_entry_option(&pair_name))
+            .and_then(|entry| entry.commit_reveal)?;
+
+        let now = env::block_timestamp();
+        let round = commit_reveal.config.round_at(now);
+        let (phase, phase_ends_at) = commit_reveal.config.phase_at(now);
+        Some(RoundState {
+            round,
+            phase,
+            phase_ends_at,
+            commitment: commit_reveal
+                .commitment
+                .filter(|commitment| commitment.round == round),
+            revealed_round: commit_reveal.revealed_round,
+        })
+    }
+}
+
+/// Commit-reveal tests
+#[cfg(test)]
+mod tests {
+
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+    use price_pair::STORAGE_COST;
+
+    use super::*;
+
+    fn alice() -> AccountId {
+        "alice.near".parse().unwrap()
+    }
+    fn bob() -> AccountId {
+        "bob.near".parse().unwrap()
+    }
+    fn carol() -> AccountId {
+        "carol.near".parse().unwrap()
+    }
+
+    fn get_context(
+        predecessor_account_id: AccountId,
+        current_account_id: AccountId,
+    ) -> VMContextBuilder {
+        let mut builder = VMContextBuilder::new();
+        builder
+            .current_account_id(current_account_id.clone())
+            .signer_account_id("robert.testnet".parse().unwrap())
+            .predecessor_account_id(predecessor_account_id.clone())
+            .attached_deposit(STORAGE_COST);
+        builder
+    }
+
+    /// Creates a contract where alice pushes ETH/USD and bob commits and reveals it
+    /// in rounds of 100 ns, of which the first 60 are for committing
+    fn setup() -> FPOContract {
+        testing_env!(get_context(alice(), alice()).build());
+        let mut fpo_contract = FPOContract::new();
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2000), None, None);
+
+        testing_env!(get_context(bob(), alice()).build());
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(4000), None, None);
+        fpo_contract.set_commit_reveal(
+            "ETH/USD".to_string(),
+            Some(CommitRevealConfig {
+                commit_window: 60,
+                reveal_window: 40,
+            }),
+        );
+        fpo_contract
+    }
+
+    fn commit(fpo_contract: &mut FPOContract, timestamp: u64, price: u128) {
+        testing_env!(get_context(bob(), alice())
+            .block_timestamp(timestamp)
+            .build());
+        let round = timestamp / 100;
+        fpo_contract.commit_price(
+            "ETH/USD".to_string(),
+            Base64VecU8(commitment_hash(
+                "ETH/USD",
+                &bob(),
+                round,
+                U128(price),
+                "salt",
+            )),
+            None,
+        );
+    }
+
+    fn reveal(fpo_contract: &mut FPOContract, timestamp: u64, price: u128) {
+        testing_env!(get_context(bob(), alice())
+            .block_timestamp(timestamp)
+            .build());
+        fpo_contract.reveal_price("ETH/USD".to_string(), U128(price), "salt".to_string(), None);
+    }
+
+    #[test]
+    fn commit_and_reveal() {
+        let mut fpo_contract = setup();
+        commit(&mut fpo_contract, 110, 2100);
+        let state = fpo_contract
+            .get_round_state("ETH/USD".to_string(), bob())
+            .unwrap();
+        assert_eq!(1, state.round);
+        assert_eq!(RoundPhase::Commit, state.phase);
+        assert_eq!(160, state.phase_ends_at);
+        assert!(state.commitment.is_some());
+
+        reveal(&mut fpo_contract, 170, 2100);
+        assert_eq!(
+            Some(U128(2100)),
+            fpo_contract.get_price("ETH/USD".to_string(), &bob())
+        );
+        let state = fpo_contract
+            .get_round_state("ETH/USD".to_string(), bob())
+            .unwrap();
+        assert_eq!(RoundPhase::Reveal, state.phase);
+        assert_eq!(200, state.phase_ends_at);
+        assert_eq!(None, state.commitment);
+        assert_eq!(Some(1), state.revealed_round);
+    }
+
+    #[test]
+    #[should_panic(expected = "reveal phase has not started")]
+    fn reveal_during_commit_phase() {
+        let mut fpo_contract = setup();
+        commit(&mut fpo_contract, 110, 2100);
+        reveal(&mut fpo_contract, 150, 2100);
+    }
+
+    #[test]
+    #[should_panic(expected = "reveal window has closed")]
+    fn late_reveal() {
+        let mut fpo_contract = setup();
+        commit(&mut fpo_contract, 110, 2100);
+        reveal(&mut fpo_contract, 200, 2100);
+    }
+
+    #[test]
+    #[should_panic(expected = "commit phase has ended")]
+    fn late_commit() {
+        let mut fpo_contract = setup();
+        commit(&mut fpo_contract, 160, 2100);
+    }
+
+    #[test]
+    #[should_panic(expected = "price does not match the commitment")]
+    fn reveal_other_price() {
+        let mut fpo_contract = setup();
+        commit(&mut fpo_contract, 110, 2100);
+        reveal(&mut fpo_contract, 170, 2200);
+    }
+
+    #[test]
+    #[should_panic(expected = "price does not match the commitment")]
+    fn reveal_copied_commitment() {
+        let mut fpo_contract = setup();
+        testing_env!(get_context(carol(), alice()).build());
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(4000), None, None);
+        fpo_contract.set_commit_reveal(
+            "ETH/USD".to_string(),
+            Some(CommitRevealConfig {
+                commit_window: 60,
+                reveal_window: 40,
+            }),
+        );
+        commit(&mut fpo_contract, 110, 2100);
+
+        // carol commits bob's hash and reveals bob's price and salt once he revealed them
+        testing_env!(get_context(carol(), alice()).block_timestamp(120).build());
+        let hash = fpo_contract
+            .get_round_state("ETH/USD".to_string(), bob())
+            .unwrap()
+            .commitment
+            .unwrap()
+            .hash;
+        fpo_contract.commit_price("ETH/USD".to_string(), hash, None);
+        reveal(&mut fpo_contract, 170, 2100);
+
+        testing_env!(get_context(carol(), alice()).block_timestamp(171).build());
+        fpo_contract.reveal_price("ETH/USD".to_string(), U128(2100), "salt".to_string(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "pair uses commit-reveal, use commit_price and reveal_price")]
+    fn push_commit_reveal_pair() {
+        let mut fpo_contract = setup();
+        fpo_contract.push_data("ETH/USD".to_string(), U128(2100), None);
+    }
+
+    #[test]
+    fn missing_reveal() {
+        let mut fpo_contract = setup();
+        commit(&mut fpo_contract, 110, 2100);
+        reveal(&mut fpo_contract, 170, 2100);
+
+        // the commitment of round 2 is never revealed
+        commit(&mut fpo_contract, 210, 2500);
+        commit(&mut fpo_contract, 310, 2200);
+        assert_eq!(
+            Some(U128(2100)),
+            fpo_contract.get_price("ETH/USD".to_string(), &bob())
+        );
+        let state = fpo_contract
+            .get_round_state("ETH/USD".to_string(), bob())
+            .unwrap();
+        assert_eq!(3, state.round);
+        assert_eq!(Some(1), state.revealed_round);
+    }
+
+    #[test]
+    fn aggregate_revealed_prices() {
+        let mut fpo_contract = setup();
+        let pairs = vec!["ETH/USD".to_string(); 2];
+
+        // the initial price was never revealed
+        testing_env!(get_context(bob(), alice()).block_timestamp(110).build());
+        assert_eq!(
+            Some(U128(2000)),
+            fpo_contract.aggregate_avg(pairs.clone(), vec![alice(), bob()], 0, None)
+        );
+
+        commit(&mut fpo_contract, 110, 2200);
+        reveal(&mut fpo_contract, 170, 2200);
+        assert_eq!(
+            Some(U128(2100)),
+            fpo_contract.aggregate_avg(pairs.clone(), vec![alice(), bob()], 0, None)
+        );
+
+        // still eligible during the next round, left out once a reveal was missed
+        testing_env!(get_context(bob(), alice()).block_timestamp(299).build());
+        assert_eq!(
+            Some(U128(2100)),
+            fpo_contract.aggregate_avg(pairs.clone(), vec![alice(), bob()], 0, None)
+        );
+        testing_env!(get_context(bob(), alice()).block_timestamp(300).build());
+        assert_eq!(
+            Some(U128(2000)),
+            fpo_contract.aggregate_avg(pairs, vec![alice(), bob()], 0, None)
+        );
+    }
+}
@@ -0,0 +1,407 @@
+use crate::*;
+use near_sdk::json_types::Base64VecU8;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{CurveType, PublicKey, Timestamp};
+use signed_report::{verify_signature, MAX_REPORT_AGE};
+
+const MAX_COMMITTEE_SIZE: usize = 32; // keeps verifying a report within the gas limit
+
+/// Signer keys of a feed, of which `threshold` have to sign every update
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Committee {
+    pub signers: Vec<PublicKey>,
+    pub threshold: u16,
+}
+
+/// Price update signed off-chain by a committee, the signatures cover its borsh serialization
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Debug, Clone)]
+pub struct CommitteeReport {
+    pub oracle: AccountId, // Contract the report is meant for
+    pub provider: AccountId,
+    pub pair: String,
+    pub price: U128,
+    pub timestamp: Timestamp, // Time of signing
+    pub round_id: u64,        // Round the report updates, one after the current round of the pair
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct CommitteeSignature {
+    pub signer: PublicKey,
+    pub signature: Base64VecU8,
+}
+
+/// Private contract methods
+impl FPOContract {
+    /// Panics if a pair only accepts reports signed by its committee
+    pub fn assert_no_committee(&self, pair_name: &str) {
+        assert!(
+            self.committees.get(&pair_name.to_string()).is_none(),
+            "pair only accepts committee reports"
+        );
+    }
+}
+
+/// Public contract methods
+#[near_bindgen]
+impl FPOContract {
+    /// Sets or clears the committee that has to sign the updates of a price pair by a provider.
+    /// The attached deposit pays for storing the signers
+    #[payable]
+    pub fn set_committee(&mut self, pair: String, committee: Option<Committee>) {
+        let initial_storage_usage = env::storage_usage();
+        let pair_name = format!("{}:{}", pair, env::predecessor_account_id());
+        self.get_provider_expect(&env::predecessor_account_id())
+            .get_entry_expect(&pair_name);
+
+        match committee {
+            Some(committee) => {
+                assert!(
+                    committee.threshold > 0
+                        && usize::from(committee.threshold) <= committee.signers.len(),
+                    "threshold must be between 1 and the number of signers"
+                );
+                assert!(
+                    committee.signers.len() <= MAX_COMMITTEE_SIZE,
+                    "committee can have at most {} signers",
+                    MAX_COMMITTEE_SIZE
+                );
+                for (i, signer) in committee.signers.iter().enumerate() {
+                    assert_eq!(
+                        signer.curve_type(),
+                        CurveType::ED25519,
+                        "signing key must be an ed25519 key"
+                    );
+                    assert!(!committee.signers[..i].contains(signer), "duplicate signer");
+                }
+                self.committees.insert(&pair_name, &committee);
+            }
+            None => {
+                self.committees.remove(&pair_name);
+            }
+        }
+
+        // check for storage deposit
+        let storage_cost = env::storage_byte_cost()
+            * u128::from(env::storage_usage().saturating_sub(initial_storage_usage));
+        assert!(
+            storage_cost <= env::attached_deposit(),
+            "Insufficient storage, need {}",
+            storage_cost
+        );
+    }
+
+    /// Returns the committee of a price pair by a provider
+    pub fn get_committee(&self, pair: String, provider: AccountId) -> Option<Committee> {
+        self.committees.get(&format!("{}:{}", pair, provider))
+    }
+
+    /// Applies a report signed by at least `threshold` committee members, which can be
+    /// submitted by any account. The attached deposit pays for storing the signers
+    #[payable]
+    pub fn submit_committee_report(
+        &mut self,
+        report: CommitteeReport,
+        signatures: Vec<CommitteeSignature>,
+    ) {
+        self.assert_not_paused();
+        let initial_storage_usage = env::storage_usage();
+        assert_eq!(
+            report.oracle,
+            env::current_account_id(),
+            "report is for another oracle"
+        );
+        let now = env::block_timestamp();
+        assert!(report.timestamp <= now, "report timestamp is in the future");
+        assert!(
+            now - report.timestamp <= MAX_REPORT_AGE,
+            "report has expired"
+        );
+
+        let pair_name = format!("{}:{}", report.pair, report.provider);
+        let committee = self
+            .committees
+            .get(&pair_name)
+            .expect("pair has no committee");
+        let provider = self.get_provider_expect(&report.provider);
+        provider.assert_active();
+        provider.assert_no_commit_reveal(&pair_name);
+        let entry = provider.get_entry_expect(&pair_name);
+        assert_eq!(
+            report.round_id,
+            entry.round_id + 1,
+            "report is not for the next round"
+        );
+
+        // every committee member counts once, invalid signatures are ignored
+        let message = report.try_to_vec().expect("failed to serialize report");
+        let mut attested_by: Vec<PublicKey> = vec![];
+        for signature in signatures.iter() {
+            if committee.signers.contains(&signature.signer)
+                && !attested_by.contains(&signature.signer)
+                && verify_signature(&signature.signer, &message, &signature.signature.0)
+            {
+                attested_by.push(signature.signer.clone());
+            }
+        }
+        assert!(
+            attested_by.len() >= usize::from(committee.threshold),
+            "not enough valid signatures"
+        );
+
+        let accepted = self.update_price(
+            provider,
+            &report.pair,
+            report.price,
+            &report.provider,
+            &report.provider,
+        );
+        // a report held back by the circuit breaker doesn't create the round it attests
+        if accepted {
+            self.attestations
+                .insert(&format!("{}:{}", pair_name, report.round_id), &attested_by);
+        }
+
+        let storage_cost =
+            env::storage_byte_cost() * u128::from(env::storage_usage() - initial_storage_usage);
+        assert!(
+            storage_cost <= env::attached_deposit(),
+            "Insufficient storage, need {}",
+            storage_cost
+        );
+    }
+
+    /// Returns the committee members that signed a round of a price pair by a provider
+    pub fn get_attestation(
+        &self,
+        pair: String,
+        provider: AccountId,
+        round_id: u64,
+    ) -> Option<Vec<PublicKey>> {
+        self.attestations
+            .get(&format!("{}:{}:{}", pair, provider, round_id))
+    }
+}
+
+/// Committee tests
+#[cfg(test)]
+mod tests {
+
+    use breaker::BreakerConfig;
+    use ed25519_dalek::{ExpandedSecretKey, PublicKey as Ed25519PublicKey, SecretKey};
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+    use price_pair::STORAGE_COST;
+    use std::convert::TryFrom;
+
+    use super::*;
+
+    fn alice() -> AccountId {
+        "alice.near".parse().unwrap()
+    }
+    fn bob() -> AccountId {
+        "bob.near".parse().unwrap()
+    }
+    fn fpo() -> AccountId {
+        "fpo.near".parse().unwrap()
+    }
+
+    fn get_context(
+        predecessor_account_id: AccountId,
+        current_account_id: AccountId,
+    ) -> VMContextBuilder {
+        let mut builder = VMContextBuilder::new();
+        builder
+            .current_account_id(current_account_id.clone())
+            .signer_account_id("robert.testnet".parse().unwrap())
+            .predecessor_account_id(predecessor_account_id.clone())
+            .attached_deposit(STORAGE_COST);
+        builder
+    }
+
+    fn secret_key(seed: u8) -> SecretKey {
+        SecretKey::from_bytes(&[seed; 32]).unwrap()
+    }
+
+    fn public_key(seed: u8) -> PublicKey {
+        let mut bytes = vec![0];
+        bytes.extend_from_slice(Ed25519PublicKey::from(&secret_key(seed)).as_bytes());
+        PublicKey::try_from(bytes).unwrap()
+    }
+
+    fn report(round_id: u64, price: u128) -> CommitteeReport {
+        CommitteeReport {
+            oracle: fpo(),
+            provider: alice(),
+            pair: "ETH/USD".to_string(),
+            price: U128(price),
+            timestamp: 100,
+            round_id,
+        }
+    }
+
+    fn sign(report: &CommitteeReport, seed: u8) -> CommitteeSignature {
+        let secret_key = secret_key(seed);
+        let signature = ExpandedSecretKey::from(&secret_key).sign(
+            &report.try_to_vec().unwrap(),
+            &Ed25519PublicKey::from(&secret_key),
+        );
+        CommitteeSignature {
+            signer: public_key(seed),
+            signature: Base64VecU8(signature.to_bytes().to_vec()),
+        }
+    }
+
+    /// Creates a contract where ETH/USD by alice needs 2 of 3 committee signatures
+    fn setup() -> FPOContract {
+        testing_env!(get_context(alice(), fpo()).build());
+        let mut fpo_contract = FPOContract::new();
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2000), None, None);
+        fpo_contract.set_committee(
+            "ETH/USD".to_string(),
+            Some(Committee {
+                signers: vec![public_key(1), public_key(2), public_key(3)],
+                threshold: 2,
+            }),
+        );
+
+        testing_env!(get_context(bob(), fpo()).block_timestamp(150).build());
+        fpo_contract
+    }
+
+    #[test]
+    fn submit_committee_report() {
+        let mut fpo_contract = setup();
+        let report = report(1, 2500);
+        fpo_contract
+            .submit_committee_report(report.clone(), vec![sign(&report, 1), sign(&report, 3)]);
+
+        let entry = fpo_contract
+            .get_entry("ETH/USD".to_string(), alice())
+            .unwrap();
+        assert_eq!(U128(2500), entry.price);
+        assert_eq!(1, entry.round_id);
+        assert_eq!(
+            Some(vec![public_key(1), public_key(3)]),
+            fpo_contract.get_attestation("ETH/USD".to_string(), alice(), 1)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "not enough valid signatures")]
+    fn duplicate_signatures() {
+        let mut fpo_contract = setup();
+        let report = report(1, 2500);
+        fpo_contract
+            .submit_committee_report(report.clone(), vec![sign(&report, 1), sign(&report, 1)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "not enough valid signatures")]
+    fn signature_outside_committee() {
+        let mut fpo_contract = setup();
+        let report = report(1, 2500);
+        fpo_contract
+            .submit_committee_report(report.clone(), vec![sign(&report, 1), sign(&report, 4)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "not enough valid signatures")]
+    fn signatures_over_different_prices() {
+        let mut fpo_contract = setup();
+        let signed = report(1, 2500);
+        let other = report(1, 2600);
+        fpo_contract
+            .submit_committee_report(signed.clone(), vec![sign(&signed, 1), sign(&other, 2)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "report is not for the next round")]
+    fn replay_committee_report() {
+        let mut fpo_contract = setup();
+        let report = report(1, 2500);
+        let signatures = vec![sign(&report, 1), sign(&report, 2)];
+        fpo_contract.submit_committee_report(report.clone(), signatures.clone());
+        fpo_contract.submit_committee_report(report, signatures);
+    }
+
+    #[test]
+    #[should_panic(expected = "pair only accepts committee reports")]
+    fn push_committee_pair() {
+        let mut fpo_contract = setup();
+        testing_env!(get_context(alice(), fpo()).build());
+        fpo_contract.push_data("ETH/USD".to_string(), U128(2500), None);
+    }
+
+    #[test]
+    fn pending_report_is_not_attested() {
+        let mut fpo_contract = setup();
+        testing_env!(get_context(alice(), fpo()).build());
+        fpo_contract.set_breaker(
+            "ETH/USD".to_string(),
+            Some(BreakerConfig {
+                max_deviation: Some(1000),
+                confirmation_window: Some(100),
+                ..Default::default()
+            }),
+        );
+
+        testing_env!(get_context(bob(), fpo()).block_timestamp(150).build());
+        let report = report(1, 2500);
+        fpo_contract
+            .submit_committee_report(report.clone(), vec![sign(&report, 1), sign(&report, 3)]);
+
+        assert_eq!(
+            0,
+            fpo_contract
+                .get_entry("ETH/USD".to_string(), alice())
+                .unwrap()
+                .round_id
+        );
+        assert_eq!(
+            None,
+            fpo_contract.get_attestation("ETH/USD".to_string(), alice(), 1)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "committee can have at most 32 signers")]
+    fn committee_too_large() {
+        let mut fpo_contract = setup();
+        testing_env!(get_context(alice(), fpo()).build());
+        fpo_contract.set_committee(
+            "ETH/USD".to_string(),
+            Some(Committee {
+                signers: (1..=33).map(public_key).collect(),
+                threshold: 2,
+            }),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Insufficient storage")]
+    fn set_committee_without_deposit() {
+        let mut fpo_contract = setup();
+        testing_env!(get_context(alice(), fpo()).attached_deposit(0).build());
+        fpo_contract.set_committee(
+            "ETH/USD".to_string(),
+            Some(Committee {
+                signers: vec![public_key(1), public_key(2), public_key(3), public_key(4)],
+                threshold: 2,
+            }),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "threshold must be between 1 and the number of signers")]
+    fn threshold_above_signers() {
+        let mut fpo_contract = setup();
+        testing_env!(get_context(alice(), fpo()).build());
+        fpo_contract.set_committee(
+            "ETH/USD".to_string(),
+            Some(Committee {
+                signers: vec![public_key(1)],
+                threshold: 2,
+            }),
+        );
+    }
+}
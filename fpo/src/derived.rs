@@ -0,0 +1,357 @@
+use crate::*;
+use admin::Role;
+use aggregate::AggregateOptions;
+use math::Rounding;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::Timestamp;
+use price_pair::PriceEntry;
+
+#[derive(
+    BorshDeserialize, BorshSerialize, Deserialize, Serialize, Debug, Clone, Copy, PartialEq,
+)]
+pub enum Aggregation {
+    Mean,
+    Median,
+}
+
+#[derive(
+    BorshDeserialize, BorshSerialize, Deserialize, Serialize, Debug, Clone, Copy, PartialEq,
+)]
+pub enum DerivedOp {
+    Multiply,
+    Divide,
+}
+
+/// An aggregated price that is multiplied with or divides the derived price
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct DerivedLeg {
+    pub pairs: Vec<String>,
+    pub providers: Vec<AccountId>,
+    pub aggregation: Aggregation,
+    pub op: DerivedOp,
+}
+
+/// A price computed from existing pairs at read time, e.g. ETH/EUR = ETH/USD ÷ EUR/USD
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Debug, Clone)]
+pub struct DerivedPair {
+    pub legs: Vec<DerivedLeg>, // Applied in order to a starting value of 1
+    pub decimals: u16,         // Amount of decimals of the derived price
+    pub options: AggregateOptions,
+    pub max_age: Option<u64>, // Legs only use entries updated within this many nanoseconds if set
+}
+
+/// Private contract methods
+impl FPOContract {
+    /// Returns the aggregated price of a leg in the largest amount of decimals of its entries
+    /// updated at or after `min_last_update`, None if none of its entries has such a price
+    fn get_leg_price(
+        &self,
+        leg: &DerivedLeg,
+        options: &AggregateOptions,
+        min_last_update: Timestamp,
+    ) -> Option<(u128, u16)> {
+        let entries: Vec<(PriceEntry, u128)> = self
+            .collect_entries(&leg.pairs, &leg.providers, min_last_update, options)
+            .into_iter()
+            .flatten()
+            // the heartbeat takes the place of `min_last_update` for pairs that have one
+            .filter(|(entry, _)| entry.last_update >= min_last_update)
+            .collect();
+        let decimals = entries.iter().map(|(entry, _)| entry.decimals).max()?;
+        let mut prices = aggregate::rescale_prices(&entries, decimals);
+        let price = match leg.aggregation {
+            Aggregation::Mean => aggregate::mean_price(&prices, options.bond_weighted),
+            Aggregation::Median => aggregate::median_price(&mut prices, options.bond_weighted),
+        }?;
+        Some((price.0, decimals))
+    }
+}
+
+/// Public contract methods
+#[near_bindgen]
+impl FPOContract {
+    /// Defines a derived pair, replacing any existing definition with the same name
+    #[payable]
+    pub fn set_derived_pair(&mut self, name: String, derived_pair: DerivedPair) {
+        self.assert_role(Role::Admin);
+        assert!(!derived_pair.legs.is_empty(), "derived pair needs a leg");
+        for leg in derived_pair.legs.iter() {
+            assert!(!leg.pairs.is_empty(), "derived pair leg needs a pair");
+            assert_eq!(
+                leg.pairs.len(),
+                leg.providers.len(),
+                "pairs and provider should be of equal length"
+            );
+        }
+        let initial_storage_usage = env::storage_usage();
+
+        self.derived_pairs.insert(&name, &derived_pair);
+
+        // check for storage deposit
+        let storage_cost = env::storage_byte_cost()
+            * u128::from(env::storage_usage().saturating_sub(initial_storage_usage));
+        assert!(
+            storage_cost <= env::attached_deposit(),
+            "Insufficient storage, need {}",
+            storage_cost
+        );
+    }
+
+    /// Removes a derived pair
+    pub fn remove_derived_pair(&mut self, name: String) {
+        self.assert_role(Role::Admin);
+        assert!(
+            self.derived_pairs.remove(&name).is_some(),
+            "derived pair does not exist"
+        );
+    }
+
+    /// Returns the definition of a derived pair
+    pub fn get_derived_pair(&self, name: String) -> Option<DerivedPair> {
+        self.derived_pairs.get(&name)
+    }
+
+    /// Computes a derived price from the current aggregates of its legs, returning None if the
+    /// derived pair doesn't exist, a leg has no recent enough price or the result doesn't fit
+    pub fn get_derived_price(&self, name: String) -> Option<U128> {
+        let derived_pair = self.derived_pairs.get(&name)?;
+        let min_last_update = derived_pair
+            .max_age
+            .map(|max_age| env::block_timestamp().saturating_sub(max_age))
+            .unwrap_or(0);
+
+        let mut price = math::pow10(derived_pair.decimals)?;
+        for leg in derived_pair.legs.iter() {
+            let (leg_price, leg_decimals) =
+                self.get_leg_price(leg, &derived_pair.options, min_last_update)?;
+            let leg_unit = math::pow10(leg_decimals)?;
+            price = match leg.op {
+                DerivedOp::Multiply => math::mul_div(price, leg_price, leg_unit, Rounding::Down)?,
+                DerivedOp::Divide => math::mul_div(price, leg_unit, leg_price, Rounding::Down)?,
+            };
+        }
+        Some(U128(price))
+    }
+}
+
+/// Derived pair tests
+#[cfg(test)]
+mod tests {
+
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+    use price_pair::STORAGE_COST;
+
+    use super::*;
+
+    fn alice() -> AccountId {
+        "alice.near".parse().unwrap()
+    }
+    fn bob() -> AccountId {
+        "bob.near".parse().unwrap()
+    }
+    fn carol() -> AccountId {
+        "carol.near".parse().unwrap()
+    }
+    fn dave() -> AccountId {
+        "dave.near".parse().unwrap()
+    }
+
+    fn get_context(
+        predecessor_account_id: AccountId,
+        current_account_id: AccountId,
+    ) -> VMContextBuilder {
+        let mut builder = VMContextBuilder::new();
+        builder
+            .current_account_id(current_account_id.clone())
+            .signer_account_id("robert.testnet".parse().unwrap())
+            .predecessor_account_id(predecessor_account_id.clone())
+            .attached_deposit(STORAGE_COST);
+        builder
+    }
+
+    fn leg(pair: &str, providers: Vec<AccountId>, op: DerivedOp) -> DerivedLeg {
+        DerivedLeg {
+            pairs: vec![pair.to_string(); providers.len()],
+            providers,
+            aggregation: Aggregation::Median,
+            op,
+        }
+    }
+
+    /// Creates a contract where alice and bob provide ETH/USD and EUR/USD with different decimals
+    fn setup() -> FPOContract {
+        testing_env!(get_context(alice(), alice()).build());
+        let mut fpo_contract = FPOContract::new();
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(300_000_000_000), None, None);
+        fpo_contract.create_pair("EUR/USD".to_string(), 4, U128(12_000), None, None);
+
+        testing_env!(get_context(bob(), alice()).build());
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(300_000_000_000), None, None);
+        fpo_contract.create_pair("EUR/USD".to_string(), 4, U128(12_000), None, None);
+
+        testing_env!(get_context(alice(), alice()).build());
+        fpo_contract
+    }
+
+    #[test]
+    fn derived_quotient() {
+        let mut fpo_contract = setup();
+        fpo_contract.set_derived_pair(
+            "ETH/EUR".to_string(),
+            DerivedPair {
+                legs: vec![
+                    leg("ETH/USD", vec![alice(), bob()], DerivedOp::Multiply),
+                    leg("EUR/USD", vec![alice(), bob()], DerivedOp::Divide),
+                ],
+                decimals: 6,
+                options: AggregateOptions::default(),
+                max_age: None,
+            },
+        );
+
+        // 3000 / 1.2 = 2500
+        assert_eq!(
+            Some(U128(2_500_000_000)),
+            fpo_contract.get_derived_price("ETH/EUR".to_string())
+        );
+    }
+
+    #[test]
+    fn derived_product() {
+        let mut fpo_contract = setup();
+        fpo_contract.set_derived_pair(
+            "ETH/USD*EUR/USD".to_string(),
+            DerivedPair {
+                legs: vec![
+                    leg("ETH/USD", vec![alice()], DerivedOp::Multiply),
+                    leg("EUR/USD", vec![bob()], DerivedOp::Multiply),
+                ],
+                decimals: 2,
+                options: AggregateOptions::default(),
+                max_age: None,
+            },
+        );
+
+        // 3000 * 1.2 = 3600
+        assert_eq!(
+            Some(U128(360_000)),
+            fpo_contract.get_derived_price("ETH/USD*EUR/USD".to_string())
+        );
+    }
+
+    #[test]
+    fn derived_missing_leg() {
+        let mut fpo_contract = setup();
+        fpo_contract.set_derived_pair(
+            "BTC/EUR".to_string(),
+            DerivedPair {
+                legs: vec![
+                    leg("BTC/USD", vec![alice()], DerivedOp::Multiply),
+                    leg("EUR/USD", vec![alice()], DerivedOp::Divide),
+                ],
+                decimals: 6,
+                options: AggregateOptions::default(),
+                max_age: None,
+            },
+        );
+        assert_eq!(None, fpo_contract.get_derived_price("BTC/EUR".to_string()));
+    }
+
+    #[test]
+    fn derived_leg_with_mixed_decimals() {
+        let mut fpo_contract = setup();
+        testing_env!(get_context(carol(), alice()).build());
+        fpo_contract.create_pair("EUR/USD".to_string(), 6, U128(1_300_000), None, None);
+
+        testing_env!(get_context(alice(), alice()).build());
+        fpo_contract.set_derived_pair(
+            "ETH/EUR".to_string(),
+            DerivedPair {
+                legs: vec![
+                    leg("ETH/USD", vec![alice()], DerivedOp::Multiply),
+                    // dave never created the pair and is left out instead of failing the leg
+                    leg("EUR/USD", vec![alice(), carol(), dave()], DerivedOp::Divide),
+                ],
+                decimals: 6,
+                options: AggregateOptions::default(),
+                max_age: None,
+            },
+        );
+
+        // 3000 / median(1.2, 1.3) = 2400
+        assert_eq!(
+            Some(U128(2_400_000_000)),
+            fpo_contract.get_derived_price("ETH/EUR".to_string())
+        );
+    }
+
+    #[test]
+    fn remove_derived_pair() {
+        let mut fpo_contract = setup();
+        let derived_pair = DerivedPair {
+            legs: vec![leg("ETH/USD", vec![alice()], DerivedOp::Multiply)],
+            decimals: 8,
+            options: AggregateOptions::default(),
+            max_age: None,
+        };
+        fpo_contract.set_derived_pair("ETH".to_string(), derived_pair.clone());
+        assert_eq!(
+            Some(derived_pair.legs),
+            fpo_contract
+                .get_derived_pair("ETH".to_string())
+                .map(|derived_pair| derived_pair.legs)
+        );
+
+        fpo_contract.remove_derived_pair("ETH".to_string());
+        assert!(fpo_contract.get_derived_pair("ETH".to_string()).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "missing role Admin")]
+    fn set_derived_pair_not_admin() {
+        let mut fpo_contract = setup();
+        testing_env!(get_context(bob(), alice()).build());
+        fpo_contract.set_derived_pair(
+            "ETH".to_string(),
+            DerivedPair {
+                legs: vec![leg("ETH/USD", vec![alice()], DerivedOp::Multiply)],
+                decimals: 8,
+                options: AggregateOptions::default(),
+                max_age: None,
+            },
+        );
+    }
+
+    #[test]
+    fn derived_leg_within_max_age() {
+        testing_env!(get_context(alice(), alice()).block_timestamp(100).build());
+        let mut fpo_contract = FPOContract::new();
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(300_000_000_000), None, None);
+        testing_env!(get_context(bob(), alice()).block_timestamp(200).build());
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(310_000_000_000), None, None);
+
+        testing_env!(get_context(alice(), alice()).block_timestamp(250).build());
+        let derived_pair = DerivedPair {
+            legs: vec![leg("ETH/USD", vec![alice(), bob()], DerivedOp::Multiply)],
+            decimals: 2,
+            options: AggregateOptions::default(),
+            max_age: Some(100),
+        };
+        fpo_contract.set_derived_pair("ETH".to_string(), derived_pair);
+        // alice's price is too old
+        assert_eq!(
+            Some(U128(310_000)),
+            fpo_contract.get_derived_price("ETH".to_string())
+        );
+
+        testing_env!(get_context(alice(), alice()).block_timestamp(400).build());
+        assert_eq!(None, fpo_contract.get_derived_price("ETH".to_string()));
+    }
+
+    #[test]
+    fn unknown_derived_pair() {
+        let fpo_contract = setup();
+        assert_eq!(None, fpo_contract.get_derived_price("ETH/EUR".to_string()));
+    }
+}
@@ -0,0 +1,457 @@
+use crate::*;
+use admin::Role;
+use events::{DisputeOpenedData, DisputeResolvedData, FPOEvent};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{Promise, Timestamp};
+use price_pair::PriceEntry;
+
+const ROUND_HISTORY: u64 = 16; // previous rounds of a pair that are kept for disputes
+const ROUND_RECORD_STORAGE: u64 = 40 + 16 + 8; // storage of a round besides its key: record overhead, price and last update
+
+/// Dispute settings used until an admin changes them
+pub const DEFAULT_DISPUTE_CONFIG: DisputeConfig = DisputeConfig {
+    window: 60 * 60 * 1_000_000_000,                        // 1 hour
+    deposit: U128(1_000_000_000_000_000_000_000_000),       // 1 NEAR
+    slash_amount: U128(10_000_000_000_000_000_000_000_000), // 10 NEAR
+};
+
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct DisputeConfig {
+    pub window: u64,        // Nanoseconds after an update during which it can be disputed
+    pub deposit: U128,      // Deposit required to open a dispute
+    pub slash_amount: U128, // Slashed from the provider's bond if a dispute is upheld
+}
+
+#[derive(
+    BorshDeserialize, BorshSerialize, Deserialize, Serialize, Debug, Clone, Copy, PartialEq,
+)]
+pub enum DisputeStatus {
+    Open,     // Waiting for an arbiter
+    Upheld,   // The provider was slashed and the deposit returned
+    Rejected, // The deposit was forfeited
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Dispute {
+    pub disputer: AccountId,
+    pub deposit: U128,
+    pub price: U128, // Price of the disputed round
+    pub opened_at: Timestamp,
+    pub status: DisputeStatus,
+}
+
+/// Price of a previous round, kept so it can be disputed within the dispute window
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone, PartialEq)]
+pub struct RoundRecord {
+    pub price: U128,
+    pub last_update: Timestamp,
+}
+
+/// Returns the most storage the round history of a pair can use, which is paid for when the
+/// pair is created
+pub fn round_history_storage_usage(pair_name: &str) -> u64 {
+    // keys are "{pair_name}:{round_id}" strings after the prefix of `rounds`
+    let max_key_len = 1 + 4 + pair_name.len() + 1 + u64::MAX.to_string().len();
+    ROUND_HISTORY * (max_key_len as u64 + ROUND_RECORD_STORAGE)
+}
+
+/// Private contract methods
+impl FPOContract {
+    /// Keeps the round of an entry that is about to be replaced, forgetting the round that
+    /// falls out of the history
+    pub fn record_round(&mut self, pair_name: &str, entry: &PriceEntry) {
+        self.rounds.insert(
+            &format!("{}:{}", pair_name, entry.round_id),
+            &RoundRecord {
+                price: entry.price,
+                last_update: entry.last_update,
+            },
+        );
+        if let Some(expired) = entry.round_id.checked_sub(ROUND_HISTORY) {
+            self.rounds.remove(&format!("{}:{}", pair_name, expired));
+        }
+    }
+
+    /// Forgets the previous rounds of an entry
+    pub fn remove_rounds(&mut self, pair_name: &str, entry: &PriceEntry) {
+        let oldest = entry.round_id.saturating_sub(ROUND_HISTORY);
+        for round_id in oldest..entry.round_id {
+            self.rounds.remove(&format!("{}:{}", pair_name, round_id));
+        }
+    }
+
+    /// Forgets the disputes of a pair so a pair created under the same name starts without
+    /// them, panics if one of them is still open
+    pub fn remove_disputes(&mut self, pair_name: &str) {
+        let round_ids = self
+            .pair_disputes
+            .remove(&pair_name.to_string())
+            .unwrap_or_default();
+        for round_id in round_ids {
+            if let Some(dispute) = self.disputes.remove(&format!("{}:{}", pair_name, round_id)) {
+                assert_ne!(
+                    dispute.status,
+                    DisputeStatus::Open,
+                    "pair has an open dispute"
+                );
+            }
+        }
+    }
+
+    /// Checks if a round has an open or upheld dispute
+    pub fn is_round_disputed(&self, pair_name: &str, round_id: u64) -> bool {
+        self.disputes
+            .get(&format!("{}:{}", pair_name, round_id))
+            .map(|dispute| dispute.status != DisputeStatus::Rejected)
+            .unwrap_or(false)
+    }
+}
+
+/// Public contract methods
+#[near_bindgen]
+impl FPOContract {
+    /// Disputes a round of a provider's pair, which is possible within the dispute window
+    /// after the update of the round and requires attaching the dispute deposit
+    #[payable]
+    pub fn open_dispute(&mut self, pair: String, provider: AccountId, round_id: u64) {
+        self.assert_not_paused();
+        assert!(
+            env::attached_deposit() >= self.dispute_config.deposit.0,
+            "attach at least the dispute deposit"
+        );
+
+        let pair_name = format!("{}:{}", pair, provider);
+        let entry = self
+            .get_provider_expect(&provider)
+            .get_entry_expect(&pair_name);
+        let round = if entry.round_id == round_id {
+            Some(RoundRecord {
+                price: entry.price,
+                last_update: entry.last_update,
+            })
+        } else {
+            self.rounds.get(&format!("{}:{}", pair_name, round_id))
+        };
+        let round = round
+            .filter(|round| {
+                env::block_timestamp()
+                    <= round.last_update.saturating_add(self.dispute_config.window)
+            })
+            .expect("round can no longer be disputed");
+
+        let key = format!("{}:{}", pair_name, round_id);
+        assert!(
+            self.disputes.get(&key).is_none(),
+            "round is already disputed"
+        );
+        self.disputes.insert(
+            &key,
+            &Dispute {
+                disputer: env::predecessor_account_id(),
+                deposit: U128(env::attached_deposit()),
+                price: round.price,
+                opened_at: env::block_timestamp(),
+                status: DisputeStatus::Open,
+            },
+        );
+        let mut round_ids = self.pair_disputes.get(&pair_name).unwrap_or_default();
+        round_ids.push(round_id);
+        self.pair_disputes.insert(&pair_name, &round_ids);
+
+        FPOEvent::DisputeOpened(vec![DisputeOpenedData {
+            pair: &pair,
+            provider: &provider,
+            round_id,
+            disputer: &env::predecessor_account_id(),
+        }])
+        .emit();
+    }
+
+    /// Resolves an open dispute. Upholding it slashes the provider and returns the deposit,
+    /// rejecting it forfeits the deposit
+    pub fn resolve_dispute(
+        &mut self,
+        pair: String,
+        provider: AccountId,
+        round_id: u64,
+        upheld: bool,
+    ) -> Option<Promise> {
+        self.assert_role(Role::Arbiter);
+        let key = format!("{}:{}:{}", pair, provider, round_id);
+        let mut dispute = self.disputes.get(&key).expect("no dispute for this round");
+        assert_eq!(
+            dispute.status,
+            DisputeStatus::Open,
+            "dispute is already resolved"
+        );
+
+        let refund = if upheld {
+            dispute.status = DisputeStatus::Upheld;
+            self.slashed_funds += self.slash_bond(&provider, self.dispute_config.slash_amount.0);
+            Some(Promise::new(dispute.disputer.clone()).transfer(dispute.deposit.0))
+        } else {
+            dispute.status = DisputeStatus::Rejected;
+            self.slashed_funds += dispute.deposit.0;
+            None
+        };
+        self.disputes.insert(&key, &dispute);
+
+        FPOEvent::DisputeResolved(vec![DisputeResolvedData {
+            pair: &pair,
+            provider: &provider,
+            round_id,
+            status: dispute.status,
+        }])
+        .emit();
+        refund
+    }
+
+    /// Returns the dispute of a round, if any
+    pub fn get_dispute(&self, pair: String, provider: AccountId, round_id: u64) -> Option<Dispute> {
+        self.disputes
+            .get(&format!("{}:{}:{}", pair, provider, round_id))
+    }
+
+    /// Checks if a round has an open or upheld dispute
+    pub fn is_disputed(&self, pair: String, provider: AccountId, round_id: u64) -> bool {
+        self.is_round_disputed(&format!("{}:{}", pair, provider), round_id)
+    }
+
+    /// Sets the dispute window, deposit and slash amount
+    pub fn set_dispute_config(&mut self, config: DisputeConfig) {
+        self.assert_role(Role::Admin);
+        self.dispute_config = config;
+    }
+
+    /// Returns the dispute window, deposit and slash amount
+    pub fn get_dispute_config(&self) -> DisputeConfig {
+        self.dispute_config.clone()
+    }
+}
+
+/// Dispute tests
+#[cfg(test)]
+mod tests {
+
+    use aggregate::AggregateOptions;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+    use price_pair::STORAGE_COST;
+
+    use super::*;
+
+    fn alice() -> AccountId {
+        "alice.near".parse().unwrap()
+    }
+    fn bob() -> AccountId {
+        "bob.near".parse().unwrap()
+    }
+    fn carol() -> AccountId {
+        "carol.near".parse().unwrap()
+    }
+
+    fn get_context(
+        predecessor_account_id: AccountId,
+        current_account_id: AccountId,
+    ) -> VMContextBuilder {
+        let mut builder = VMContextBuilder::new();
+        builder
+            .current_account_id(current_account_id.clone())
+            .signer_account_id("robert.testnet".parse().unwrap())
+            .predecessor_account_id(predecessor_account_id.clone())
+            .attached_deposit(STORAGE_COST);
+        builder
+    }
+
+    /// Creates a contract where alice and bob provide ETH/USD, bob bonded 100 yocto and
+    /// disputes cost 10 yocto within 50 ns
+    fn setup() -> FPOContract {
+        testing_env!(get_context(alice(), alice()).block_timestamp(100).build());
+        let mut fpo_contract = FPOContract::new();
+        fpo_contract.set_dispute_config(DisputeConfig {
+            window: 50,
+            deposit: U128(10),
+            slash_amount: U128(30),
+        });
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2000), None, None);
+
+        testing_env!(get_context(bob(), alice()).block_timestamp(100).build());
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(4000), None, None);
+        testing_env!(get_context(bob(), alice())
+            .attached_deposit(100)
+            .block_timestamp(100)
+            .build());
+        fpo_contract.bond();
+        fpo_contract
+    }
+
+    fn open_dispute(fpo_contract: &mut FPOContract, timestamp: u64) {
+        testing_env!(get_context(carol(), alice())
+            .attached_deposit(10)
+            .block_timestamp(timestamp)
+            .build());
+        fpo_contract.open_dispute("ETH/USD".to_string(), bob(), 0);
+    }
+
+    #[test]
+    fn uphold_dispute() {
+        let mut fpo_contract = setup();
+        open_dispute(&mut fpo_contract, 150);
+        assert!(fpo_contract.is_disputed("ETH/USD".to_string(), bob(), 0));
+
+        testing_env!(get_context(alice(), alice()).build());
+        fpo_contract.resolve_dispute("ETH/USD".to_string(), bob(), 0, true);
+
+        let dispute = fpo_contract
+            .get_dispute("ETH/USD".to_string(), bob(), 0)
+            .unwrap();
+        assert_eq!(DisputeStatus::Upheld, dispute.status);
+        assert_eq!(U128(4000), dispute.price);
+        assert_eq!(U128(70), fpo_contract.get_bond(bob()).unwrap().bond);
+        assert_eq!(U128(30), fpo_contract.get_slashed_funds());
+        assert!(fpo_contract.is_disputed("ETH/USD".to_string(), bob(), 0));
+    }
+
+    #[test]
+    fn reject_dispute() {
+        let mut fpo_contract = setup();
+        open_dispute(&mut fpo_contract, 150);
+
+        testing_env!(get_context(alice(), alice()).build());
+        fpo_contract.grant_role(bob(), Role::Arbiter);
+        testing_env!(get_context(bob(), alice()).build());
+        fpo_contract.resolve_dispute("ETH/USD".to_string(), bob(), 0, false);
+
+        assert_eq!(U128(100), fpo_contract.get_bond(bob()).unwrap().bond);
+        assert_eq!(U128(10), fpo_contract.get_slashed_funds());
+        assert!(!fpo_contract.is_disputed("ETH/USD".to_string(), bob(), 0));
+    }
+
+    #[test]
+    #[should_panic(expected = "round can no longer be disputed")]
+    fn dispute_after_window() {
+        let mut fpo_contract = setup();
+        open_dispute(&mut fpo_contract, 151);
+    }
+
+    #[test]
+    fn dispute_previous_round() {
+        let mut fpo_contract = setup();
+        testing_env!(get_context(bob(), alice()).block_timestamp(120).build());
+        fpo_contract.push_data("ETH/USD".to_string(), U128(4100), None);
+        open_dispute(&mut fpo_contract, 130);
+
+        let dispute = fpo_contract
+            .get_dispute("ETH/USD".to_string(), bob(), 0)
+            .unwrap();
+        assert_eq!(U128(4000), dispute.price);
+        assert!(!fpo_contract.is_disputed("ETH/USD".to_string(), bob(), 1));
+    }
+
+    #[test]
+    #[should_panic(expected = "round can no longer be disputed")]
+    fn dispute_previous_round_after_window() {
+        let mut fpo_contract = setup();
+        testing_env!(get_context(bob(), alice()).block_timestamp(140).build());
+        fpo_contract.push_data("ETH/USD".to_string(), U128(4100), None);
+        // round 1 is still within the window, round 0 isn't
+        open_dispute(&mut fpo_contract, 151);
+    }
+
+    #[test]
+    #[should_panic(expected = "round can no longer be disputed")]
+    fn dispute_forgotten_round() {
+        let mut fpo_contract = setup();
+        testing_env!(get_context(bob(), alice()).block_timestamp(100).build());
+        for price in 0..ROUND_HISTORY + 1 {
+            fpo_contract.push_data("ETH/USD".to_string(), U128(4001 + u128::from(price)), None);
+        }
+        open_dispute(&mut fpo_contract, 110);
+    }
+
+    #[test]
+    fn round_history_is_paid_for() {
+        let mut fpo_contract = setup();
+        testing_env!(get_context(bob(), alice()).block_timestamp(100).build());
+        let storage_used_before = env::storage_usage();
+        for price in 0..2 * ROUND_HISTORY {
+            fpo_contract.push_data("ETH/USD".to_string(), U128(4001 + u128::from(price)), None);
+        }
+        assert!(
+            env::storage_usage() - storage_used_before
+                <= round_history_storage_usage(&format!("ETH/USD:{}", bob()))
+        );
+    }
+
+    #[test]
+    fn recreated_pair_has_no_disputes() {
+        let mut fpo_contract = setup();
+        open_dispute(&mut fpo_contract, 120);
+        testing_env!(get_context(alice(), alice()).build());
+        fpo_contract.resolve_dispute("ETH/USD".to_string(), bob(), 0, true);
+
+        testing_env!(get_context(bob(), alice()).block_timestamp(130).build());
+        fpo_contract.remove_pair("ETH/USD".to_string());
+        assert_eq!(
+            None,
+            fpo_contract.get_dispute("ETH/USD".to_string(), bob(), 0)
+        );
+
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(4000), None, None);
+        assert!(!fpo_contract.is_disputed("ETH/USD".to_string(), bob(), 0));
+        open_dispute(&mut fpo_contract, 140);
+        assert!(fpo_contract.is_disputed("ETH/USD".to_string(), bob(), 0));
+    }
+
+    #[test]
+    #[should_panic(expected = "pair has an open dispute")]
+    fn remove_pair_with_open_dispute() {
+        let mut fpo_contract = setup();
+        open_dispute(&mut fpo_contract, 120);
+        testing_env!(get_context(bob(), alice()).block_timestamp(130).build());
+        fpo_contract.remove_pair("ETH/USD".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "attach at least the dispute deposit")]
+    fn dispute_without_deposit() {
+        let mut fpo_contract = setup();
+        testing_env!(get_context(carol(), alice())
+            .attached_deposit(9)
+            .block_timestamp(120)
+            .build());
+        fpo_contract.open_dispute("ETH/USD".to_string(), bob(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "missing role Arbiter")]
+    fn resolve_without_role() {
+        let mut fpo_contract = setup();
+        open_dispute(&mut fpo_contract, 120);
+        fpo_contract.resolve_dispute("ETH/USD".to_string(), bob(), 0, true);
+    }
+
+    #[test]
+    fn aggregate_exclude_disputed() {
+        let mut fpo_contract = setup();
+        open_dispute(&mut fpo_contract, 120);
+
+        let pairs = vec!["ETH/USD".to_string(); 2];
+        assert_eq!(
+            Some(U128(3000)),
+            fpo_contract.aggregate_avg(pairs.clone(), vec![alice(), bob()], 0, None)
+        );
+        assert_eq!(
+            Some(U128(2000)),
+            fpo_contract.aggregate_avg(
+                pairs,
+                vec![alice(), bob()],
+                0,
+                Some(AggregateOptions {
+                    exclude_disputed: true,
+                    ..Default::default()
+                })
+            )
+        );
+    }
+}
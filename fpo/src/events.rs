@@ -0,0 +1,230 @@
+use crate::*;
+use callbacks::PriceType;
+use dispute::DisputeStatus;
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json;
+use near_sdk::Timestamp;
+use provider::ProviderStatus;
+
+const EVENT_STANDARD: &str = "fpo";
+const EVENT_STANDARD_VERSION: &str = "1.0.0";
+
+/// NEP-297 events emitted by the contract
+#[derive(Serialize, Debug)]
+#[serde(tag = "event", content = "data")]
+#[serde(rename_all = "snake_case")]
+pub enum FPOEvent<'a> {
+    PairCreated(Vec<PairCreatedData<'a>>),
+    PricePushed(Vec<PricePushedData<'a>>),
+    PairRemoved(Vec<PairRemovedData<'a>>),
+    FeeUpdated(Vec<FeeUpdatedData<'a>>),
+    PriceForwarded(Vec<PriceForwardedData<'a>>),
+    ProviderStatusUpdated(Vec<ProviderStatusUpdatedData<'a>>),
+    #[serde(rename = "circuit_breaker_tripped")]
+    BreakerTripped(Vec<BreakerTrippedData<'a>>),
+    ProviderSlashed(Vec<ProviderSlashedData<'a>>),
+    DisputeOpened(Vec<DisputeOpenedData<'a>>),
+    DisputeResolved(Vec<DisputeResolvedData<'a>>),
+    ForwardResolved(Vec<ForwardResolvedData<'a>>),
+}
+
+#[derive(Serialize, Debug)]
+pub struct PairCreatedData<'a> {
+    pub pair: &'a str,
+    pub provider: &'a AccountId,
+    pub price: U128,
+    pub decimals: u16,
+    pub timestamp: Timestamp,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PricePushedData<'a> {
+    pub pair: &'a str,
+    pub provider: &'a AccountId,
+    pub price: U128,
+    pub decimals: u16,
+    pub timestamp: Timestamp,
+    pub round_id: u64,
+    pub pusher: &'a AccountId,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PairRemovedData<'a> {
+    pub pair: &'a str,
+    pub provider: &'a AccountId,
+}
+
+#[derive(Serialize, Debug)]
+pub struct FeeUpdatedData<'a> {
+    pub provider: &'a AccountId,
+    pub fee: U128,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PriceForwardedData<'a> {
+    pub sender_id: &'a AccountId,
+    pub request_id: u64,
+    pub receiver_id: &'a AccountId,
+    pub price_type: &'a PriceType,
+    pub pairs: &'a [String],
+    pub providers: &'a [AccountId],
+    pub results: &'a [Option<U128>],
+}
+
+#[derive(Serialize, Debug)]
+pub struct ForwardResolvedData<'a> {
+    pub sender_id: &'a AccountId,
+    pub request_id: u64,
+    pub receiver_id: &'a AccountId,
+    pub price_type: &'a PriceType,
+    pub delivered: bool, // Whether the price consumer handled the prices without failing
+    pub refund: U128,    // Fee returned to the sender if the delivery failed
+}
+
+#[derive(Serialize, Debug)]
+pub struct ProviderStatusUpdatedData<'a> {
+    pub provider: &'a AccountId,
+    pub status: ProviderStatus,
+}
+
+#[derive(Serialize, Debug)]
+pub struct BreakerTrippedData<'a> {
+    pub pair: &'a str,
+    pub provider: &'a AccountId,
+    pub price: U128,         // Price that stays in effect
+    pub pending_price: U128, // Price waiting for confirmation
+    pub timestamp: Timestamp,
+    pub pusher: &'a AccountId,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ProviderSlashedData<'a> {
+    pub provider: &'a AccountId,
+    pub amount: U128,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DisputeOpenedData<'a> {
+    pub pair: &'a str,
+    pub provider: &'a AccountId,
+    pub round_id: u64,
+    pub disputer: &'a AccountId,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DisputeResolvedData<'a> {
+    pub pair: &'a str,
+    pub provider: &'a AccountId,
+    pub round_id: u64,
+    pub status: DisputeStatus,
+}
+
+#[derive(Serialize, Debug)]
+struct EventLog<'a> {
+    standard: &'static str,
+    version: &'static str,
+    #[serde(flatten)]
+    event: &'a FPOEvent<'a>,
+}
+
+impl FPOEvent<'_> {
+    /// Logs the event as `EVENT_JSON:{...}`
+    pub fn emit(&self) {
+        let log = EventLog {
+            standard: EVENT_STANDARD,
+            version: EVENT_STANDARD_VERSION,
+            event: self,
+        };
+        env::log_str(&format!(
+            "EVENT_JSON:{}",
+            serde_json::to_string(&log).expect("failed to serialize event")
+        ));
+    }
+}
+
+/// Event tests
+#[cfg(test)]
+mod tests {
+
+    use near_sdk::test_utils::{get_logs, VMContextBuilder};
+    use near_sdk::testing_env;
+    use price_pair::STORAGE_COST;
+
+    use super::*;
+
+    fn alice() -> AccountId {
+        "alice.near".parse().unwrap()
+    }
+
+    fn get_context(
+        predecessor_account_id: AccountId,
+        current_account_id: AccountId,
+    ) -> VMContextBuilder {
+        let mut builder = VMContextBuilder::new();
+        builder
+            .current_account_id(current_account_id.clone())
+            .signer_account_id("robert.testnet".parse().unwrap())
+            .predecessor_account_id(predecessor_account_id.clone())
+            .attached_deposit(STORAGE_COST)
+            .block_timestamp(100);
+        builder
+    }
+
+    #[test]
+    fn emit_pair_created_and_price_pushed() {
+        let context = get_context(alice(), alice());
+        testing_env!(context.build());
+        let mut fpo_contract = FPOContract::new();
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2500), None, None);
+        fpo_contract.push_data("ETH/USD".to_string(), U128(3000), None);
+
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"fpo","version":"1.0.0","event":"pair_created","data":[{"pair":"ETH/USD","provider":"alice.near","price":"2500","decimals":8,"timestamp":100}]}"#,
+                r#"EVENT_JSON:{"standard":"fpo","version":"1.0.0","event":"price_pushed","data":[{"pair":"ETH/USD","provider":"alice.near","price":"3000","decimals":8,"timestamp":100,"round_id":1,"pusher":"alice.near"}]}"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn emit_pair_removed_and_fee_updated() {
+        let context = get_context(alice(), alice());
+        testing_env!(context.build());
+        let mut fpo_contract = FPOContract::new();
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2500), None, None);
+        fpo_contract.set_fee(U128(10));
+        fpo_contract.remove_pair("ETH/USD".to_string());
+
+        let logs = get_logs();
+        assert_eq!(
+            logs[1],
+            r#"EVENT_JSON:{"standard":"fpo","version":"1.0.0","event":"fee_updated","data":[{"provider":"alice.near","fee":"10"}]}"#
+        );
+        assert_eq!(
+            logs[2],
+            r#"EVENT_JSON:{"standard":"fpo","version":"1.0.0","event":"pair_removed","data":[{"pair":"ETH/USD","provider":"alice.near"}]}"#
+        );
+    }
+
+    #[test]
+    fn emit_price_forwarded() {
+        let context = get_context(alice(), alice());
+        testing_env!(context.build());
+        let mut fpo_contract = FPOContract::new();
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2500), None, None);
+        fpo_contract.get_price_call(
+            "ETH/USD".to_string(),
+            alice(),
+            "consumer.near".parse().unwrap(),
+            None,
+            None,
+            None,
+        );
+
+        assert_eq!(
+            get_logs()[1],
+            r#"EVENT_JSON:{"standard":"fpo","version":"1.0.0","event":"price_forwarded","data":[{"sender_id":"alice.near","request_id":1,"receiver_id":"consumer.near","price_type":"Single","pairs":["ETH/USD"],"providers":["alice.near"],"results":["2500"]}]}"#
+        );
+    }
+}
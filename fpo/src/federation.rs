@@ -0,0 +1,282 @@
+use crate::*;
+use aggregate::AggregateOptions;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::serde_json;
+use near_sdk::{ext_contract, Balance, Gas, Promise, PromiseResult, Timestamp};
+
+const GAS_PER_ORACLE: Gas = Gas(10_000_000_000_000); // gas for the aggregation on each deployment
+const GAS_FOR_COMBINE: Gas = Gas(5_000_000_000_000); // gas for on_federated_aggregate()
+const ZERO_BALANCE: Balance = 0;
+
+/// How the results of several deployments are combined into one price
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum FederatedMethod {
+    Mean,
+    Median,
+}
+
+/// Aggregations of other FPO deployments
+#[ext_contract(ext_oracle)]
+pub trait Oracle {
+    fn aggregate_avg(
+        &self,
+        pairs: Vec<String>,
+        providers: Vec<AccountId>,
+        min_last_update: Timestamp,
+        options: Option<AggregateOptions>,
+    ) -> Option<U128>;
+    fn aggregate_median(
+        &self,
+        pairs: Vec<String>,
+        providers: Vec<AccountId>,
+        min_last_update: Timestamp,
+        options: Option<AggregateOptions>,
+    ) -> Option<U128>;
+}
+
+/// Callbacks of this contract
+#[ext_contract(ext_federation)]
+pub trait FederationResolver {
+    fn on_federated_aggregate(&self, method: FederatedMethod) -> Option<U128>;
+}
+
+/// Public contract methods
+#[near_bindgen]
+impl FPOContract {
+    /// Aggregates the given pairs and providers on every oracle in parallel with `method`
+    /// and combines their results with `method` again, leaving out oracles without an answer
+    pub fn aggregate_federated(
+        &self,
+        oracles: Vec<AccountId>,
+        pairs: Vec<String>,
+        providers: Vec<AccountId>,
+        min_last_update: Timestamp,
+        method: FederatedMethod,
+    ) -> Promise {
+        assert!(!oracles.is_empty(), "no oracles to aggregate");
+        for (i, oracle) in oracles.iter().enumerate() {
+            assert!(!oracles[..i].contains(oracle), "duplicate oracle");
+        }
+        let needed = GAS_PER_ORACLE.0 * oracles.len() as u64 + GAS_FOR_COMBINE.0;
+        let available = env::prepaid_gas() - env::used_gas();
+        assert!(
+            needed <= available.0,
+            "not enough gas attached, the aggregation needs {} but only {} are left",
+            needed,
+            available.0
+        );
+
+        oracles
+            .into_iter()
+            .map(|oracle| match method {
+                FederatedMethod::Mean => ext_oracle::aggregate_avg(
+                    pairs.clone(),
+                    providers.clone(),
+                    min_last_update,
+                    None,
+                    oracle,
+                    ZERO_BALANCE,
+                    GAS_PER_ORACLE,
+                ),
+                FederatedMethod::Median => ext_oracle::aggregate_median(
+                    pairs.clone(),
+                    providers.clone(),
+                    min_last_update,
+                    None,
+                    oracle,
+                    ZERO_BALANCE,
+                    GAS_PER_ORACLE,
+                ),
+            })
+            .reduce(|joined, promise| joined.and(promise))
+            .unwrap()
+            .then(ext_federation::on_federated_aggregate(
+                method,
+                env::current_account_id(),
+                ZERO_BALANCE,
+                GAS_FOR_COMBINE,
+            ))
+    }
+
+    /// Combines the results of the oracles, None if none of them had an answer
+    #[private]
+    pub fn on_federated_aggregate(&self, method: FederatedMethod) -> Option<U128> {
+        let mut prices: Vec<u128> = (0..env::promise_results_count())
+            .filter_map(|index| match env::promise_result(index) {
+                PromiseResult::Successful(value) => serde_json::from_slice::<Option<U128>>(&value)
+                    .ok()
+                    .flatten(),
+                _ => None,
+            })
+            .map(|price| price.0)
+            .collect();
+
+        if prices.is_empty() {
+            return None;
+        }
+
+        match method {
+            FederatedMethod::Mean => Some(math::mean(&prices)),
+            FederatedMethod::Median => Some(math::median(&mut prices)),
+        }
+    }
+}
+
+/// Federated aggregation tests
+#[cfg(test)]
+mod tests {
+
+    use near_sdk::mock::VmAction;
+    use near_sdk::test_utils::{get_created_receipts, VMContextBuilder};
+    use near_sdk::testing_env;
+    use near_sdk::{RuntimeFeesConfig, VMConfig};
+
+    use super::*;
+
+    fn alice() -> AccountId {
+        "alice.near".parse().unwrap()
+    }
+    fn bob() -> AccountId {
+        "bob.near".parse().unwrap()
+    }
+
+    fn get_context(
+        predecessor_account_id: AccountId,
+        current_account_id: AccountId,
+    ) -> VMContextBuilder {
+        let mut builder = VMContextBuilder::new();
+        builder
+            .current_account_id(current_account_id.clone())
+            .signer_account_id("robert.testnet".parse().unwrap())
+            .predecessor_account_id(predecessor_account_id.clone());
+        builder
+    }
+
+    fn combine(results: Vec<PromiseResult>, method: FederatedMethod) -> Option<U128> {
+        testing_env!(get_context(alice(), alice()).build());
+        let fpo_contract = FPOContract::new();
+        testing_env!(
+            get_context(alice(), alice()).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            results,
+        );
+        fpo_contract.on_federated_aggregate(method)
+    }
+
+    fn answer(price: Option<u128>) -> PromiseResult {
+        PromiseResult::Successful(serde_json::to_vec(&price.map(U128)).unwrap())
+    }
+
+    /// Returns the receiver and method of every function call scheduled so far
+    fn scheduled_calls() -> Vec<(String, String)> {
+        get_created_receipts()
+            .into_iter()
+            .flat_map(|receipt| {
+                let receiver_id = receipt.receiver_id.to_string();
+                receipt
+                    .actions
+                    .into_iter()
+                    .filter_map(move |action| match action {
+                        VmAction::FunctionCall { function_name, .. } => {
+                            Some((receiver_id.clone(), function_name))
+                        }
+                        _ => None,
+                    })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn combine_median() {
+        let results = vec![answer(Some(2000)), answer(Some(2100)), answer(Some(5000))];
+        assert_eq!(Some(U128(2100)), combine(results, FederatedMethod::Median));
+    }
+
+    #[test]
+    fn combine_mean() {
+        let results = vec![answer(Some(2000)), answer(Some(3000))];
+        assert_eq!(Some(U128(2500)), combine(results, FederatedMethod::Mean));
+    }
+
+    #[test]
+    fn combine_skips_missing_answers() {
+        let results = vec![answer(None), PromiseResult::Failed, answer(Some(3000))];
+        assert_eq!(Some(U128(3000)), combine(results, FederatedMethod::Median));
+    }
+
+    #[test]
+    fn combine_without_answers() {
+        let results = vec![answer(None), PromiseResult::Failed];
+        assert_eq!(None, combine(results, FederatedMethod::Mean));
+    }
+
+    #[test]
+    fn aggregate_federated() {
+        testing_env!(get_context(alice(), alice()).build());
+        let fpo_contract = FPOContract::new();
+        fpo_contract.aggregate_federated(
+            vec![alice(), bob()],
+            vec!["ETH/USD".to_string()],
+            vec![alice()],
+            0,
+            FederatedMethod::Median,
+        );
+        assert_eq!(
+            vec![
+                ("alice.near".to_string(), "aggregate_median".to_string()),
+                ("bob.near".to_string(), "aggregate_median".to_string()),
+                (
+                    "alice.near".to_string(),
+                    "on_federated_aggregate".to_string()
+                ),
+            ],
+            scheduled_calls()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "duplicate oracle")]
+    fn aggregate_federated_with_duplicate_oracles() {
+        testing_env!(get_context(alice(), alice()).build());
+        let fpo_contract = FPOContract::new();
+        fpo_contract.aggregate_federated(
+            vec![alice(), bob(), alice()],
+            vec!["ETH/USD".to_string()],
+            vec![alice()],
+            0,
+            FederatedMethod::Median,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "no oracles to aggregate")]
+    fn aggregate_federated_without_oracles() {
+        testing_env!(get_context(alice(), alice()).build());
+        let fpo_contract = FPOContract::new();
+        fpo_contract.aggregate_federated(
+            vec![],
+            vec!["ETH/USD".to_string()],
+            vec![alice()],
+            0,
+            FederatedMethod::Median,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "not enough gas attached")]
+    fn aggregate_federated_with_little_gas() {
+        testing_env!(get_context(alice(), alice())
+            .prepaid_gas(Gas(12_000_000_000_000))
+            .build());
+        let fpo_contract = FPOContract::new();
+        fpo_contract.aggregate_federated(
+            vec![alice(), bob()],
+            vec!["ETH/USD".to_string()],
+            vec![alice()],
+            0,
+            FederatedMethod::Median,
+        );
+    }
+}
@@ -1,35 +1,126 @@
+mod admin;
 mod aggregate;
+mod bond;
+mod breaker;
 mod callbacks;
+mod committee;
+mod commit_reveal;
+mod derived;
+mod dispute;
+mod events;
+mod federation;
 mod math;
+mod metadata;
 mod price_pair;
 mod provider;
+mod pusher;
+mod registry;
+mod reputation;
+mod routing;
+mod signed_report;
+mod tokens;
+mod upgrade;
+use crate::admin::Role;
+use crate::bond::DEFAULT_UNBONDING_DELAY;
+use crate::committee::Committee;
+use crate::dispute::{Dispute, DisputeConfig, RoundRecord, DEFAULT_DISPUTE_CONFIG};
+use crate::metadata::PairMetadata;
+use crate::derived::DerivedPair;
 use crate::provider::Provider;
+use crate::tokens::TokenAsset;
+use crate::upgrade::{StateVersion, VersionedProvider, CURRENT_STATE_VERSION};
 
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
 use near_sdk::collections::LookupMap;
 use near_sdk::json_types::U128;
-use near_sdk::{env, near_bindgen, AccountId, BorshStorageKey, PanicOnDefault};
+use near_sdk::{env, near_bindgen, AccountId, BorshStorageKey, PanicOnDefault, PublicKey};
 
 /// Global variables
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
 pub struct FPOContract {
-    pub providers: LookupMap<AccountId, Provider>, // maps:  AccountId => Provider
+    pub providers: LookupMap<AccountId, VersionedProvider>, // maps:  AccountId => Provider
+    pub state_version: StateVersion, // follows `providers`, the only field of the unversioned state
+    pub owner: AccountId,
+    pub pending_owner: Option<AccountId>, // set by transfer_ownership() until accepted
+    pub roles: LookupMap<AccountId, Vec<Role>>, // maps:  AccountId => granted roles
+    pub paused: bool,
+    pub permissioned: bool, // only registered providers can create pairs if set
+    pub derived_pairs: LookupMap<String, DerivedPair>, // maps:  name => DerivedPair
+    pub pair_providers: LookupMap<String, Vec<AccountId>>, // maps:  pair => providers publishing it
+    pub asset_pairs: LookupMap<String, Vec<String>>, // maps:  asset => pairs it is part of
+    pub unbonding_delay: u64, // time in nanoseconds between unbonding and withdrawing a bond
+    pub slashed_funds: u128,  // slashed bonds and forfeited dispute deposits that can be withdrawn by the owner
+    pub disputes: LookupMap<String, Dispute>, // maps:  "{pair}:{provider}:{round}" => Dispute
+    pub pair_disputes: LookupMap<String, Vec<u64>>, // maps:  "{pair}:{provider}" => rounds with a dispute
+    pub dispute_config: DisputeConfig,
+    pub committees: LookupMap<String, Committee>, // maps:  "{pair}:{provider}" => Committee
+    pub attestations: LookupMap<String, Vec<PublicKey>>, // maps:  "{pair}:{provider}:{round}" => signers
+    pub request_nonce: u64, // id of the last forward, shared by all callers so forwards don't grow the storage
+    pub pair_metadata: LookupMap<String, PairMetadata>, // maps:  "{pair}:{provider}" => PairMetadata
+    pub token_assets: LookupMap<AccountId, TokenAsset>, // maps:  NEP-141 token contract => TokenAsset
+    pub rounds: LookupMap<String, RoundRecord>, // maps:  "{pair}:{provider}:{round}" => RoundRecord of a previous round
+    pub metadata_asset_counts: LookupMap<String, u32>, // maps:  asset => number of pairs described with it
+    pub metadata_asset_list: LookupMap<String, String>, // maps:  "{asset}:{INDEX}" => "{pair}:{provider}" described with it
+    pub metadata_asset_indices: LookupMap<String, u32>, // maps:  "{asset}:{pair}:{provider}" => INDEX in `metadata_asset_list`
 }
 
 /// LookupMap keys
 #[derive(BorshStorageKey, BorshSerialize)]
 enum FPOStorageKeys {
     Providers,
+    Roles,
+    DerivedPairs,
+    PairProviders,
+    AssetPairs,
+    Disputes,
+    Committees,
+    Attestations,
+    PairMetadata,
+    TokenAssets,
+    PairList,
+    PairIndices,
+    Scores,
+    Rounds,
+    MetadataAssetCounts,
+    MetadataAssetList,
+    MetadataAssetIndices,
+    ProviderPairs, // unversioned providers keep their pairs under the prefix of `Providers`
+    Pushers,
+    PairDisputes,
 }
 
 /// Constructor
 #[near_bindgen]
 impl FPOContract {
+    /// Initializes the contract with the predecessor as owner
     #[init]
     pub fn new() -> Self {
         Self {
             providers: LookupMap::new(FPOStorageKeys::Providers),
+            state_version: CURRENT_STATE_VERSION,
+            owner: env::predecessor_account_id(),
+            pending_owner: None,
+            roles: LookupMap::new(FPOStorageKeys::Roles),
+            paused: false,
+            permissioned: false,
+            derived_pairs: LookupMap::new(FPOStorageKeys::DerivedPairs),
+            pair_providers: LookupMap::new(FPOStorageKeys::PairProviders),
+            asset_pairs: LookupMap::new(FPOStorageKeys::AssetPairs),
+            unbonding_delay: DEFAULT_UNBONDING_DELAY,
+            slashed_funds: 0,
+            disputes: LookupMap::new(FPOStorageKeys::Disputes),
+            pair_disputes: LookupMap::new(FPOStorageKeys::PairDisputes),
+            dispute_config: DEFAULT_DISPUTE_CONFIG,
+            committees: LookupMap::new(FPOStorageKeys::Committees),
+            attestations: LookupMap::new(FPOStorageKeys::Attestations),
+            request_nonce: 0,
+            pair_metadata: LookupMap::new(FPOStorageKeys::PairMetadata),
+            token_assets: LookupMap::new(FPOStorageKeys::TokenAssets),
+            rounds: LookupMap::new(FPOStorageKeys::Rounds),
+            metadata_asset_counts: LookupMap::new(FPOStorageKeys::MetadataAssetCounts),
+            metadata_asset_list: LookupMap::new(FPOStorageKeys::MetadataAssetList),
+            metadata_asset_indices: LookupMap::new(FPOStorageKeys::MetadataAssetIndices),
         }
     }
 }
@@ -1,15 +1,348 @@
 use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+
+/// Largest power of ten that fits in a u128
+const MAX_DECIMALS: u32 = 38;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum Rounding {
+    Down,    // Towards zero
+    Up,      // Away from zero
+    Nearest, // Half away from zero
+}
+
+/// Multiplies two u128 values into a 256-bit (high, low) pair
+fn full_mul(a: u128, b: u128) -> (u128, u128) {
+    let mask = u128::from(u64::MAX);
+    let (a_hi, a_lo) = (a >> 64, a & mask);
+    let (b_hi, b_lo) = (b >> 64, b & mask);
+
+    let lo_lo = a_lo * b_lo;
+    let lo_hi = a_lo * b_hi;
+    let hi_lo = a_hi * b_lo;
+    let hi_hi = a_hi * b_hi;
+
+    let mid = (lo_lo >> 64) + (lo_hi & mask) + (hi_lo & mask);
+    let lo = (lo_lo & mask) | (mid << 64);
+    let hi = hi_hi + (lo_hi >> 64) + (hi_lo >> 64) + (mid >> 64);
+    (hi, lo)
+}
+
+/// Divides a 256-bit (high, low) pair by a u128, returning None if the divisor is zero or
+/// the quotient doesn't fit in a u128
+fn div_rem_256(hi: u128, lo: u128, divisor: u128) -> Option<(u128, u128)> {
+    if divisor == 0 || hi >= divisor {
+        return None;
+    }
+    if hi == 0 {
+        return Some((lo / divisor, lo % divisor));
+    }
+
+    // shift-subtract long division, the remainder always stays below the divisor
+    let mut remainder = hi;
+    let mut quotient = 0u128;
+    for i in (0..128).rev() {
+        let carry = remainder >> 127;
+        remainder = (remainder << 1) | ((lo >> i) & 1);
+        quotient <<= 1;
+        if carry == 1 || remainder >= divisor {
+            remainder = remainder.wrapping_sub(divisor);
+            quotient |= 1;
+        }
+    }
+    Some((quotient, remainder))
+}
+
+/// Applies a rounding mode to the result of a division
+fn round(quotient: u128, remainder: u128, divisor: u128, rounding: Rounding) -> Option<u128> {
+    let round_up = match rounding {
+        Rounding::Down => false,
+        Rounding::Up => remainder > 0,
+        Rounding::Nearest => remainder >= divisor - remainder,
+    };
+    if round_up {
+        quotient.checked_add(1)
+    } else {
+        Some(quotient)
+    }
+}
+
+/// Returns 10^decimals, None if it doesn't fit in a u128
+pub fn pow10(decimals: u16) -> Option<u128> {
+    if u32::from(decimals) > MAX_DECIMALS {
+        return None;
+    }
+    Some(10u128.pow(u32::from(decimals)))
+}
+
+/// Adds up numbers, returning None on overflow
+pub fn checked_sum(numbers: &[u128]) -> Option<u128> {
+    numbers
+        .iter()
+        .try_fold(0u128, |sum, number| sum.checked_add(*number))
+}
+
+/// Computes `a * b / divisor` with a 256-bit intermediate, returning None if the divisor is
+/// zero or the result doesn't fit in a u128
+pub fn mul_div(a: u128, b: u128, divisor: u128, rounding: Rounding) -> Option<u128> {
+    let (hi, lo) = full_mul(a, b);
+    let (quotient, remainder) = div_rem_256(hi, lo, divisor)?;
+    round(quotient, remainder, divisor, rounding)
+}
+
+/// Converts a fixed-point value from one amount of decimals to another
+pub fn rescale(
+    value: u128,
+    from_decimals: u16,
+    to_decimals: u16,
+    rounding: Rounding,
+) -> Option<u128> {
+    if to_decimals >= from_decimals {
+        value.checked_mul(pow10(to_decimals - from_decimals)?)
+    } else {
+        let divisor = pow10(from_decimals - to_decimals)?;
+        round(value / divisor, value % divisor, divisor, rounding)
+    }
+}
+
+/// Returns the mean of numbers, summed with a 256-bit intermediate so it can't overflow
+pub fn mean_rounded(numbers: &[u128], rounding: Rounding) -> U128 {
+    let (hi, lo) = numbers.iter().fold((0u128, 0u128), |(hi, lo), number| {
+        let (lo, carry) = lo.overflowing_add(*number);
+        (hi + u128::from(carry), lo)
+    });
+    let count = numbers.len() as u128;
+    let (quotient, remainder) =
+        div_rem_256(hi, lo, count).expect("cannot take the mean of an empty list");
+    // the mean is at most the largest number, so it can only overflow when rounding up
+    U128(round(quotient, remainder, count, rounding).unwrap_or(u128::MAX))
+}
+
+/// Returns the mean of numbers, rounded down
 pub fn mean(numbers: &[u128]) -> U128 {
-    let sum: u128 = numbers.iter().sum();
-    U128::from(sum as u128 / numbers.len() as u128)
+    mean_rounded(numbers, Rounding::Down)
 }
+
+/// Returns the median of numbers, the mean of the middle two for an even amount
 pub fn median(numbers: &mut [u128]) -> U128 {
     numbers.sort_unstable();
 
     let mid = numbers.len() / 2;
     if numbers.len() % 2 == 0 {
-        mean(&[numbers[mid - 1], numbers[mid]]) as U128
+        mean(&[numbers[mid - 1], numbers[mid]])
     } else {
         U128::from(numbers[mid])
     }
 }
+
+/// Returns the mean of (value, weight) pairs rounded down, None if all weights are zero
+pub fn weighted_mean(values: &[(u128, u128)]) -> Option<U128> {
+    let total_weight = checked_sum(&values.iter().map(|(_, weight)| *weight).collect::<Vec<_>>())?;
+    let mut sum = (0u128, 0u128);
+    for (value, weight) in values.iter() {
+        let (hi, lo) = full_mul(*value, *weight);
+        let (lo, carry) = sum.1.overflowing_add(lo);
+        sum = (sum.0.checked_add(hi)?.checked_add(u128::from(carry))?, lo);
+    }
+    let (quotient, _) = div_rem_256(sum.0, sum.1, total_weight)?;
+    Some(U128(quotient))
+}
+
+/// Returns the value at which half of the total weight is reached, the mean of it and the next
+/// value if exactly half is reached like `median()`. None if all weights are zero
+pub fn weighted_median(values: &mut [(u128, u128)]) -> Option<U128> {
+    values.sort_unstable();
+    let total_weight = checked_sum(&values.iter().map(|(_, weight)| *weight).collect::<Vec<_>>())?;
+    if total_weight == 0 {
+        return None;
+    }
+
+    let mut cumulative_weight = 0u128;
+    for (i, (value, weight)) in values.iter().enumerate() {
+        cumulative_weight += weight;
+        let remaining_weight = total_weight - cumulative_weight;
+        if cumulative_weight > remaining_weight {
+            return Some(U128(*value));
+        }
+        if cumulative_weight == remaining_weight {
+            let (next, _) = values[i + 1..].iter().find(|(_, weight)| *weight > 0)?;
+            return Some(mean(&[*value, *next]));
+        }
+    }
+    None
+}
+
+/// Returns the change from `from` to `to` in basis points, saturating at u128::MAX
+pub fn deviation_bps(from: u128, to: u128) -> u128 {
+    let diff = from.max(to) - from.min(to);
+    if diff == 0 {
+        return 0;
+    }
+    mul_div(diff, 10_000, from, Rounding::Down).unwrap_or(u128::MAX)
+}
+
+/// Fixed-point math tests
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    const MAX: u128 = u128::MAX;
+
+    #[test]
+    fn full_mul_max() {
+        assert_eq!((0, 0), full_mul(0, MAX));
+        assert_eq!((0, MAX), full_mul(1, MAX));
+        assert_eq!((1, MAX - 1), full_mul(2, MAX));
+        // (2^128 - 1)^2 = 2^256 - 2^129 + 1
+        assert_eq!((MAX - 1, 1), full_mul(MAX, MAX));
+    }
+
+    #[test]
+    fn div_rem_256_edges() {
+        assert_eq!(None, div_rem_256(0, 1, 0));
+        assert_eq!(None, div_rem_256(1, 0, 1));
+        assert_eq!(Some((MAX, 0)), div_rem_256(MAX - 1, 1, MAX));
+        assert_eq!(Some((1 << 127, 0)), div_rem_256(1, 0, 2));
+        assert_eq!(Some((MAX / 3, MAX % 3)), div_rem_256(0, MAX, 3));
+    }
+
+    #[test]
+    fn checked_sum_overflow() {
+        assert_eq!(Some(0), checked_sum(&[]));
+        assert_eq!(Some(MAX), checked_sum(&[MAX - 1, 1]));
+        assert_eq!(None, checked_sum(&[MAX, 1]));
+    }
+
+    #[test]
+    fn mul_div_rounding() {
+        assert_eq!(Some(3), mul_div(10, 1, 3, Rounding::Down));
+        assert_eq!(Some(4), mul_div(10, 1, 3, Rounding::Up));
+        assert_eq!(Some(3), mul_div(10, 1, 3, Rounding::Nearest));
+        assert_eq!(Some(4), mul_div(7, 1, 2, Rounding::Nearest));
+        assert_eq!(Some(3), mul_div(7, 1, 2, Rounding::Down));
+        assert_eq!(Some(2), mul_div(6, 1, 3, Rounding::Up));
+    }
+
+    #[test]
+    fn mul_div_max() {
+        assert_eq!(Some(MAX), mul_div(MAX, MAX, MAX, Rounding::Down));
+        assert_eq!(Some(MAX), mul_div(MAX, MAX, MAX, Rounding::Up));
+        assert_eq!(Some(MAX), mul_div(MAX, 1, 1, Rounding::Up));
+        assert_eq!(Some(MAX / 2), mul_div(MAX, 1, 2, Rounding::Down));
+        assert_eq!(Some(MAX / 2 + 1), mul_div(MAX, 1, 2, Rounding::Nearest));
+        assert_eq!(Some(MAX - 1), mul_div(MAX, MAX - 1, MAX, Rounding::Down));
+        assert_eq!(None, mul_div(MAX, 2, 1, Rounding::Down));
+        assert_eq!(None, mul_div(MAX, MAX, MAX - 1, Rounding::Down));
+        assert_eq!(None, mul_div(1, 1, 0, Rounding::Down));
+    }
+
+    #[test]
+    fn rescale_decimals() {
+        assert_eq!(Some(250_000), rescale(2_500, 2, 4, Rounding::Down));
+        assert_eq!(Some(2_500), rescale(2_500, 4, 4, Rounding::Down));
+        assert_eq!(Some(2), rescale(25, 1, 0, Rounding::Down));
+        assert_eq!(Some(3), rescale(25, 1, 0, Rounding::Nearest));
+        assert_eq!(Some(3), rescale(21, 1, 0, Rounding::Up));
+        assert_eq!(Some(2), rescale(24, 1, 0, Rounding::Nearest));
+        assert_eq!(None, rescale(MAX, 0, 1, Rounding::Down));
+        assert_eq!(None, rescale(1, 0, 39, Rounding::Down));
+        assert_eq!(
+            Some(MAX / pow10(38).unwrap()),
+            rescale(MAX, 38, 0, Rounding::Down)
+        );
+        assert_eq!(None, rescale(MAX, 39, 0, Rounding::Down));
+    }
+
+    #[test]
+    fn pow10_bounds() {
+        assert_eq!(Some(1), pow10(0));
+        assert_eq!(Some(100_000_000), pow10(8));
+        assert!(pow10(38).is_some());
+        assert_eq!(None, pow10(39));
+    }
+
+    #[test]
+    fn mean_max() {
+        assert_eq!(U128(MAX), mean(&[MAX, MAX, MAX]));
+        assert_eq!(U128(MAX - 1), mean(&[MAX, MAX - 2]));
+        assert_eq!(U128(MAX / 2), mean(&[MAX, 0]));
+        assert_eq!(
+            U128(MAX / 2 + 1),
+            mean_rounded(&[MAX, 0], Rounding::Nearest)
+        );
+        assert_eq!(U128(MAX / 2 + 1), mean_rounded(&[MAX, 0], Rounding::Up));
+    }
+
+    #[test]
+    fn mean_rounding() {
+        assert_eq!(U128(3500), mean(&[2000, 4000, 4000, 4000]));
+        assert_eq!(U128(1), mean(&[1, 2]));
+        assert_eq!(U128(2), mean_rounded(&[1, 2], Rounding::Nearest));
+        assert_eq!(U128(2), mean_rounded(&[1, 1, 2], Rounding::Up));
+        assert_eq!(U128(1), mean_rounded(&[1, 1, 2], Rounding::Nearest));
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot take the mean of an empty list")]
+    fn mean_empty() {
+        mean(&[]);
+    }
+
+    #[test]
+    fn median_max() {
+        assert_eq!(U128(MAX), median(&mut [MAX, MAX]));
+        assert_eq!(U128(MAX - 1), median(&mut [MAX, 0, MAX - 2, MAX]));
+        assert_eq!(U128(3000), median(&mut [4000, 2000, 2000, 4000]));
+        assert_eq!(U128(2000), median(&mut [4000, 2000, 1000]));
+    }
+
+    #[test]
+    fn weighted_mean_max() {
+        assert_eq!(Some(U128(3000)), weighted_mean(&[(2000, 1), (4000, 1)]));
+        assert_eq!(Some(U128(3500)), weighted_mean(&[(2000, 1), (4000, 3)]));
+        assert_eq!(Some(U128(MAX)), weighted_mean(&[(MAX, MAX - 1), (MAX, 1)]));
+        assert_eq!(Some(U128(2000)), weighted_mean(&[(2000, 1), (MAX, 0)]));
+        assert_eq!(None, weighted_mean(&[(2000, 0)]));
+        assert_eq!(None, weighted_mean(&[(2000, MAX), (2000, 1)]));
+    }
+
+    #[test]
+    fn weighted_median_weights() {
+        assert_eq!(
+            Some(U128(4000)),
+            weighted_median(&mut [(4000, 3), (2000, 1), (1000, 1)])
+        );
+        assert_eq!(
+            Some(U128(2000)),
+            weighted_median(&mut [(4000, 1), (2000, 1), (1000, 1)])
+        );
+        // exactly half of the weight is reached like for an even amount in `median()`
+        assert_eq!(
+            Some(U128(1500)),
+            weighted_median(&mut [(2000, 1), (1000, 1)])
+        );
+        assert_eq!(
+            Some(U128(3000)),
+            weighted_median(&mut [(4000, 2), (3500, 0), (2000, 2)])
+        );
+        assert_eq!(
+            Some(U128(MAX - 1)),
+            weighted_median(&mut [(MAX, 2), (MAX - 2, 1), (0, 1)])
+        );
+        assert_eq!(Some(U128(MAX)), weighted_median(&mut [(MAX, MAX), (0, 0)]));
+        assert_eq!(None, weighted_median(&mut [(2000, 0)]));
+    }
+
+    #[test]
+    fn deviation() {
+        assert_eq!(0, deviation_bps(0, 0));
+        assert_eq!(0, deviation_bps(MAX, MAX));
+        assert_eq!(MAX, deviation_bps(0, 1));
+        assert_eq!(1000, deviation_bps(2500, 2750));
+        assert_eq!(5000, deviation_bps(2, 1));
+        assert_eq!(9_999, deviation_bps(MAX, 1));
+        assert_eq!(MAX, deviation_bps(1, MAX));
+        assert_eq!(10_000, deviation_bps(MAX / 2, MAX - 1));
+    }
+}
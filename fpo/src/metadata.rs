@@ -0,0 +1,444 @@
+use crate::*;
+use near_sdk::serde::{Deserialize, Serialize};
+
+const MAX_METADATA_LENGTH: usize = 256; // maximum length of the assets, the description and the source
+const DEFAULT_FIND_LIMIT: u64 = 100; // results of find_pair_metadata() if no limit is given
+
+#[derive(
+    BorshDeserialize, BorshSerialize, Deserialize, Serialize, Debug, Clone, Copy, PartialEq,
+)]
+pub enum PairCategory {
+    Crypto,
+    Fiat,
+    Commodity,
+    Equity,
+    Index,
+    Other,
+}
+
+/// Describes what a price pair by a provider measures and where it comes from
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct PairMetadata {
+    pub base: String,           // Asset that is priced, e.g. "ETH"
+    pub quote: String,          // Asset the price is given in, e.g. "USD"
+    pub description: String,    // Human readable description of the feed
+    pub source: Option<String>, // URL of the data source or methodology
+    pub category: PairCategory,
+}
+
+/// A search result of `find_pair_metadata()`
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct PairMetadataEntry {
+    pub pair: String,
+    pub provider: AccountId,
+    pub metadata: PairMetadata,
+}
+
+/// Private contract methods
+impl FPOContract {
+    /// Stores the metadata of a price pair by a provider and indexes it by its assets
+    pub fn set_metadata(&mut self, pair: &str, provider: &AccountId, metadata: PairMetadata) {
+        assert!(
+            metadata.base.len() <= MAX_METADATA_LENGTH
+                && metadata.quote.len() <= MAX_METADATA_LENGTH
+                && metadata.description.len() <= MAX_METADATA_LENGTH
+                && metadata.source.as_ref().map(String::len).unwrap_or(0) <= MAX_METADATA_LENGTH,
+            "metadata is too long"
+        );
+        self.remove_metadata(pair, provider);
+
+        let pair_name = format!("{}:{}", pair, provider);
+        self.index_metadata(&metadata.base, &pair_name);
+        self.index_metadata(&metadata.quote, &pair_name);
+        self.pair_metadata.insert(&pair_name, &metadata);
+    }
+
+    /// Removes the metadata of a price pair by a provider from storage and the asset index
+    pub fn remove_metadata(&mut self, pair: &str, provider: &AccountId) {
+        let pair_name = format!("{}:{}", pair, provider);
+        let metadata = match self.pair_metadata.remove(&pair_name) {
+            Some(metadata) => metadata,
+            None => return,
+        };
+        self.unindex_metadata(&metadata.base, &pair_name);
+        self.unindex_metadata(&metadata.quote, &pair_name);
+    }
+
+    /// Adds "{pair}:{provider}" to the set of pairs described with an asset
+    fn index_metadata(&mut self, asset: &str, pair_name: &str) {
+        let key = format!("{}:{}", asset, pair_name);
+        if self.metadata_asset_indices.get(&key).is_some() {
+            return;
+        }
+        let count = self
+            .metadata_asset_counts
+            .get(&asset.to_string())
+            .unwrap_or(0);
+        self.metadata_asset_indices.insert(&key, &count);
+        self.metadata_asset_list
+            .insert(&format!("{}:{}", asset, count), &pair_name.to_string());
+        self.metadata_asset_counts
+            .insert(&asset.to_string(), &(count + 1));
+    }
+
+    /// Removes "{pair}:{provider}" from the set of pairs described with an asset, moving the
+    /// last pair into its place
+    fn unindex_metadata(&mut self, asset: &str, pair_name: &str) {
+        let index = match self
+            .metadata_asset_indices
+            .remove(&format!("{}:{}", asset, pair_name))
+        {
+            Some(index) => index,
+            None => return,
+        };
+        let count = self
+            .metadata_asset_counts
+            .get(&asset.to_string())
+            .expect("metadata index is corrupted")
+            - 1;
+        let last = self
+            .metadata_asset_list
+            .remove(&format!("{}:{}", asset, count))
+            .expect("metadata index is corrupted");
+        if index != count {
+            self.metadata_asset_indices
+                .insert(&format!("{}:{}", asset, last), &index);
+            self.metadata_asset_list
+                .insert(&format!("{}:{}", asset, index), &last);
+        }
+        if count == 0 {
+            self.metadata_asset_counts.remove(&asset.to_string());
+        } else {
+            self.metadata_asset_counts
+                .insert(&asset.to_string(), &count);
+        }
+    }
+}
+
+/// Public contract methods
+#[near_bindgen]
+impl FPOContract {
+    /// Sets the metadata of a price pair by the calling provider
+    #[payable]
+    pub fn set_pair_metadata(&mut self, pair: String, metadata: PairMetadata) {
+        self.assert_not_paused();
+        let initial_storage_usage = env::storage_usage();
+        let provider = self.get_provider_expect(&env::predecessor_account_id());
+        let pair_name = format!("{}:{}", pair, env::predecessor_account_id());
+        assert!(
+            provider.pairs.get(&pair_name).is_some(),
+            "pair does not exist"
+        );
+        self.set_metadata(&pair, &env::predecessor_account_id(), metadata);
+
+        let storage_cost = env::storage_byte_cost()
+            * u128::from(env::storage_usage().saturating_sub(initial_storage_usage));
+        assert!(
+            storage_cost <= env::attached_deposit(),
+            "Insufficient storage, need {}",
+            storage_cost
+        );
+    }
+
+    /// Returns the metadata of a price pair by a provider
+    pub fn get_pair_metadata(&self, pair: String, provider: AccountId) -> Option<PairMetadata> {
+        self.pair_metadata.get(&format!("{}:{}", pair, provider))
+    }
+
+    /// Returns the pairs whose metadata has the given base and/or quote asset. Pages through
+    /// the pairs described with the base (or the quote if no base is given) from `from_index`,
+    /// looking at `limit` of them, so a page can hold fewer results if both assets are given
+    pub fn find_pair_metadata(
+        &self,
+        base: Option<String>,
+        quote: Option<String>,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<PairMetadataEntry> {
+        let asset = base
+            .as_ref()
+            .or(quote.as_ref())
+            .expect("base or quote asset required");
+        let count = u64::from(self.metadata_asset_counts.get(asset).unwrap_or(0));
+        let from_index = from_index.unwrap_or(0).min(count);
+        let to_index = from_index
+            .saturating_add(limit.unwrap_or(DEFAULT_FIND_LIMIT))
+            .min(count);
+        (from_index..to_index)
+            .filter_map(|index| {
+                self.metadata_asset_list
+                    .get(&format!("{}:{}", asset, index))
+            })
+            .filter_map(|pair_name| {
+                let metadata = self.pair_metadata.get(&pair_name)?;
+                if matches!(&base, Some(base) if *base != metadata.base)
+                    || matches!(&quote, Some(quote) if *quote != metadata.quote)
+                {
+                    return None;
+                }
+                let (pair, provider) = pair_name.rsplit_once(':')?;
+                Some(PairMetadataEntry {
+                    pair: pair.to_string(),
+                    provider: provider.parse().ok()?,
+                    metadata,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Pair metadata tests
+#[cfg(test)]
+mod tests {
+
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+    use price_pair::STORAGE_COST;
+
+    use super::*;
+
+    fn alice() -> AccountId {
+        "alice.near".parse().unwrap()
+    }
+    fn bob() -> AccountId {
+        "bob.near".parse().unwrap()
+    }
+
+    fn get_context(
+        predecessor_account_id: AccountId,
+        current_account_id: AccountId,
+    ) -> VMContextBuilder {
+        let mut builder = VMContextBuilder::new();
+        builder
+            .current_account_id(current_account_id.clone())
+            .signer_account_id("robert.testnet".parse().unwrap())
+            .predecessor_account_id(predecessor_account_id.clone())
+            .attached_deposit(STORAGE_COST * 2); // covers the pair and its metadata
+        builder
+    }
+
+    fn metadata(base: &str, quote: &str) -> PairMetadata {
+        PairMetadata {
+            base: base.to_string(),
+            quote: quote.to_string(),
+            description: format!("{} priced in {}", base, quote),
+            source: Some("https://example.com/methodology".to_string()),
+            category: PairCategory::Crypto,
+        }
+    }
+
+    fn find(fpo_contract: &FPOContract, base: Option<&str>, quote: Option<&str>) -> Vec<String> {
+        fpo_contract
+            .find_pair_metadata(
+                base.map(str::to_string),
+                quote.map(str::to_string),
+                None,
+                None,
+            )
+            .into_iter()
+            .map(|entry| format!("{}:{}", entry.pair, entry.provider))
+            .collect()
+    }
+
+    #[test]
+    fn create_pair_with_metadata() {
+        testing_env!(get_context(alice(), alice()).build());
+        let mut fpo_contract = FPOContract::new();
+        fpo_contract.create_pair(
+            "ETH/USD".to_string(),
+            8,
+            U128(2000),
+            None,
+            Some(metadata("ETH", "USD")),
+        );
+        fpo_contract.create_pair("BTC/USD".to_string(), 8, U128(30000), None, None);
+
+        assert_eq!(
+            Some(metadata("ETH", "USD")),
+            fpo_contract.get_pair_metadata("ETH/USD".to_string(), alice())
+        );
+        assert_eq!(
+            None,
+            fpo_contract.get_pair_metadata("BTC/USD".to_string(), alice())
+        );
+    }
+
+    #[test]
+    fn update_metadata() {
+        testing_env!(get_context(alice(), alice()).build());
+        let mut fpo_contract = FPOContract::new();
+        fpo_contract.create_pair(
+            "ETH/USD".to_string(),
+            8,
+            U128(2000),
+            None,
+            Some(metadata("ETH", "USD")),
+        );
+        fpo_contract.set_pair_metadata("ETH/USD".to_string(), metadata("WETH", "USD"));
+
+        assert_eq!(
+            Some(metadata("WETH", "USD")),
+            fpo_contract.get_pair_metadata("ETH/USD".to_string(), alice())
+        );
+        assert!(find(&fpo_contract, Some("ETH"), None).is_empty());
+        assert_eq!(
+            vec!["ETH/USD:alice.near"],
+            find(&fpo_contract, Some("WETH"), None)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "pair does not exist")]
+    fn set_metadata_of_other_pair() {
+        testing_env!(get_context(alice(), alice()).build());
+        let mut fpo_contract = FPOContract::new();
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2000), None, None);
+        testing_env!(get_context(bob(), alice()).build());
+        fpo_contract.create_pair("BTC/USD".to_string(), 8, U128(30000), None, None);
+        fpo_contract.set_pair_metadata("ETH/USD".to_string(), metadata("ETH", "USD"));
+    }
+
+    #[test]
+    #[should_panic(expected = "metadata is too long")]
+    fn set_long_metadata() {
+        testing_env!(get_context(alice(), alice()).build());
+        let mut fpo_contract = FPOContract::new();
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2000), None, None);
+        let mut long = metadata("ETH", "USD");
+        long.description = "a".repeat(MAX_METADATA_LENGTH + 1);
+        fpo_contract.set_pair_metadata("ETH/USD".to_string(), long);
+    }
+
+    #[test]
+    fn find_by_base_or_quote() {
+        testing_env!(get_context(alice(), alice()).build());
+        let mut fpo_contract = FPOContract::new();
+        fpo_contract.create_pair(
+            "ETH/USD".to_string(),
+            8,
+            U128(2000),
+            None,
+            Some(metadata("ETH", "USD")),
+        );
+        fpo_contract.create_pair(
+            "BTC/USD".to_string(),
+            8,
+            U128(30000),
+            None,
+            Some(metadata("BTC", "USD")),
+        );
+        testing_env!(get_context(bob(), alice()).build());
+        fpo_contract.create_pair(
+            "ETH/BTC".to_string(),
+            8,
+            U128(6_000_000),
+            None,
+            Some(metadata("ETH", "BTC")),
+        );
+
+        assert_eq!(
+            vec!["ETH/USD:alice.near", "ETH/BTC:bob.near"],
+            find(&fpo_contract, Some("ETH"), None)
+        );
+        assert_eq!(
+            vec!["ETH/USD:alice.near", "BTC/USD:alice.near"],
+            find(&fpo_contract, None, Some("USD"))
+        );
+        assert_eq!(
+            vec!["ETH/BTC:bob.near"],
+            find(&fpo_contract, Some("ETH"), Some("BTC"))
+        );
+        // BTC is the quote of ETH/BTC, not its base
+        assert_eq!(
+            vec!["BTC/USD:alice.near"],
+            find(&fpo_contract, Some("BTC"), None)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "metadata is too long")]
+    fn set_long_asset() {
+        testing_env!(get_context(alice(), alice()).build());
+        let mut fpo_contract = FPOContract::new();
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2000), None, None);
+        let long = metadata(&"E".repeat(MAX_METADATA_LENGTH + 1), "USD");
+        fpo_contract.set_pair_metadata("ETH/USD".to_string(), long);
+    }
+
+    #[test]
+    fn find_page() {
+        testing_env!(get_context(alice(), alice()).build());
+        let mut fpo_contract = FPOContract::new();
+        for base in ["ETH", "BTC", "NEAR"].iter() {
+            fpo_contract.create_pair(
+                format!("{}/USD", base),
+                8,
+                U128(2000),
+                None,
+                Some(metadata(base, "USD")),
+            );
+        }
+        let page = |from_index, limit| -> Vec<String> {
+            fpo_contract
+                .find_pair_metadata(None, Some("USD".to_string()), from_index, limit)
+                .into_iter()
+                .map(|entry| entry.pair)
+                .collect()
+        };
+
+        assert_eq!(vec!["BTC/USD"], page(Some(1), Some(1)));
+        assert_eq!(vec!["NEAR/USD"], page(Some(2), Some(5)));
+        assert!(page(Some(3), None).is_empty());
+        assert_eq!(vec!["ETH/USD", "BTC/USD"], page(None, Some(2)));
+    }
+
+    #[test]
+    fn remove_metadata_moves_last_pair() {
+        testing_env!(get_context(alice(), alice()).build());
+        let mut fpo_contract = FPOContract::new();
+        for base in ["ETH", "BTC", "NEAR"].iter() {
+            fpo_contract.create_pair(
+                format!("{}/USD", base),
+                8,
+                U128(2000),
+                None,
+                Some(metadata(base, "USD")),
+            );
+        }
+        fpo_contract.remove_pair("ETH/USD".to_string());
+
+        assert_eq!(
+            vec!["NEAR/USD:alice.near", "BTC/USD:alice.near"],
+            find(&fpo_contract, None, Some("USD"))
+        );
+        assert!(find(&fpo_contract, Some("ETH"), None).is_empty());
+    }
+
+    #[test]
+    fn remove_pair_removes_metadata() {
+        testing_env!(get_context(alice(), alice()).build());
+        let mut fpo_contract = FPOContract::new();
+        fpo_contract.create_pair(
+            "ETH/USD".to_string(),
+            8,
+            U128(2000),
+            None,
+            Some(metadata("ETH", "USD")),
+        );
+        fpo_contract.remove_pair("ETH/USD".to_string());
+
+        assert_eq!(
+            None,
+            fpo_contract.get_pair_metadata("ETH/USD".to_string(), alice())
+        );
+        assert!(find(&fpo_contract, None, Some("USD")).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "base or quote asset required")]
+    fn find_without_asset() {
+        testing_env!(get_context(alice(), alice()).build());
+        let fpo_contract = FPOContract::new();
+        fpo_contract.find_pair_metadata(None, None, None, None);
+    }
+}
@@ -1,53 +1,232 @@
 use crate::*;
+use breaker::{BreakerConfig, PendingPrice, PriceUpdate};
+use commit_reveal::CommitReveal;
+use events::{
+    BreakerTrippedData, FPOEvent, FeeUpdatedData, PairCreatedData, PairRemovedData, PricePushedData,
+};
 use near_sdk::{
     serde::{Deserialize, Serialize},
-    Timestamp,
+    Promise, Timestamp,
 };
+use reputation::PairScore;
 
-// maximum cost of storing a new entry in create_pair() - 170 * yocto per byte (1e19 as of 2022-04-14)
+// maximum cost of storing a new entry and its round history in create_pair() - 2506 * yocto per byte (1e19 as of 2022-04-14)
 #[allow(dead_code)]
-pub const STORAGE_COST: u128 = 1_700_000_000_000_000_000_000;
+pub const STORAGE_COST: u128 = 25_060_000_000_000_000_000_000;
+
+#[derive(
+    BorshDeserialize, BorshSerialize, Deserialize, Serialize, Debug, Clone, Default, PartialEq,
+)]
+#[serde(default)]
+pub struct PairConfig {
+    pub heartbeat: Option<u64>, // Maximum time between updates in nanoseconds
+    pub deviation_threshold: Option<u16>, // Price change in basis points that warrants an update
+}
 
-#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Debug)]
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Debug, Clone)]
 pub struct PriceEntry {
-    pub price: U128,            // Last reported price
-    pub decimals: u16,          // Amount of decimals (e.g. if 2, 100 = 1.00)
-    pub last_update: Timestamp, // Time of report
+    pub price: U128,                         // Last reported price
+    pub decimals: u16,                       // Amount of decimals (e.g. if 2, 100 = 1.00)
+    pub last_update: Timestamp,              // Time of report
+    pub round_id: u64,                       // Amount of updates since the pair was created
+    pub pusher: Option<AccountId>,           // Account that pushed the last update
+    pub config: PairConfig,                  // Expected update frequency of the pair
+    pub breaker: Option<BreakerConfig>,      // Limits on updates, see `set_breaker()`
+    pub pending: Option<PendingPrice>,       // Update held back by the circuit breaker
+    pub commit_reveal: Option<CommitReveal>, // Commit-reveal rounds, see `set_commit_reveal()`
+}
+
+/// Price entry methods (internal)
+impl PriceEntry {
+    /// Checks if the entry hasn't been updated within its heartbeat, never stale without a heartbeat
+    pub fn is_stale(&self, now: Timestamp) -> bool {
+        match self.config.heartbeat {
+            Some(heartbeat) => now.saturating_sub(self.last_update) > heartbeat,
+            None => false,
+        }
+    }
+
+    /// Checks if `price` should be pushed: the entry is stale or `price` deviates from it by at
+    /// least the deviation threshold, or differs at all if the pair has no threshold
+    pub fn needs_update(&self, price: U128, now: Timestamp) -> bool {
+        if self.is_stale(now) {
+            return true;
+        }
+        match self.config.deviation_threshold {
+            Some(threshold) => math::deviation_bps(self.price.0, price.0) >= u128::from(threshold),
+            None => price != self.price,
+        }
+    }
+
+    /// Checks if the price was revealed in the current or previous round, always true
+    /// for pairs that don't use commit-reveal
+    pub fn is_revealed(&self, now: Timestamp) -> bool {
+        match &self.commit_reveal {
+            Some(commit_reveal) => commit_reveal
+                .revealed_round
+                .map(|round| round + 1 >= commit_reveal.config.round_at(now))
+                .unwrap_or(false),
+            None => true,
+        }
+    }
+}
+
+/// Private contract methods
+impl FPOContract {
+    /// Returns the provider `pusher` pushes a pair for, panics if it isn't allowed to
+    pub fn get_pushing_provider(
+        &self,
+        pair: &str,
+        provider_id: &AccountId,
+        pusher: &AccountId,
+    ) -> Provider {
+        let provider = self.get_provider_expect(provider_id);
+        provider.assert_active();
+        self.assert_no_committee(&format!("{}:{}", pair, provider_id));
+        if provider_id != pusher {
+            assert!(
+                provider.can_push(&format!("{}:{}", pusher, provider_id), pair),
+                "not allowed to push data for this provider"
+            );
+        }
+        provider
+    }
+
+    /// Sets the price of a pair, records the provider's score and emits the resulting event.
+    /// Returns false if the circuit breaker held the price back
+    pub fn update_price(
+        &mut self,
+        mut provider: Provider,
+        pair: &str,
+        price: U128,
+        provider_id: &AccountId,
+        pusher: &AccountId,
+    ) -> bool {
+        let pair_name = format!("{}:{}", pair, provider_id);
+        let previous = provider.get_entry_option(&pair_name);
+        let was_stale = previous
+            .as_ref()
+            .map(|entry| entry.is_stale(env::block_timestamp()))
+            .unwrap_or(false);
+        let update = provider.set_price(
+            pair_name.clone(),
+            price,
+            env::block_timestamp(),
+            pusher.clone(),
+        );
+        if let PriceUpdate::Accepted(entry) = &update {
+            let deviation = self
+                .consensus_price(pair, provider_id, entry.decimals)
+                .map(|consensus| math::deviation_bps(consensus, price.0));
+            provider.record_score(&pair_name, deviation, was_stale);
+            if let Some(previous) = &previous {
+                self.record_round(&pair_name, previous);
+            }
+        }
+        // pairs created before pairs were listed are indexed on their next push
+        let newly_listed = provider.list_pair(provider_id, pair);
+        self.set_provider(provider_id, provider);
+        if newly_listed {
+            self.index_pair_provider(pair, provider_id);
+        }
+
+        let entry = match update {
+            PriceUpdate::Accepted(entry) => entry,
+            PriceUpdate::Pending(entry) => {
+                FPOEvent::BreakerTripped(vec![BreakerTrippedData {
+                    pair,
+                    provider: provider_id,
+                    price: entry.price,
+                    pending_price: price,
+                    timestamp: env::block_timestamp(),
+                    pusher,
+                }])
+                .emit();
+                return false;
+            }
+        };
+
+        FPOEvent::PricePushed(vec![PricePushedData {
+            pair,
+            provider: provider_id,
+            price: entry.price,
+            decimals: entry.decimals,
+            timestamp: entry.last_update,
+            round_id: entry.round_id,
+            pusher,
+        }])
+        .emit();
+        true
+    }
 }
 
 /// Public contract methods
 #[near_bindgen]
 impl FPOContract {
-    /// Creates a new price pair by a provider
+    /// Creates a new price pair by a provider, optionally described by `metadata` which needs
+    /// a deposit for its storage on top of `STORAGE_COST`
     #[payable]
-    pub fn create_pair(&mut self, pair: String, decimals: u16, initial_price: U128) {
+    pub fn create_pair(
+        &mut self,
+        pair: String,
+        decimals: u16,
+        initial_price: U128,
+        config: Option<PairConfig>,
+        metadata: Option<PairMetadata>,
+    ) {
+        self.assert_not_paused();
         let initial_storage_usage = env::storage_usage();
 
-        let mut provider = self
-            .providers
-            .get(&env::predecessor_account_id())
-            .unwrap_or_else(Provider::new);
+        let mut provider = match self.get_provider_option(&env::predecessor_account_id()) {
+            Some(provider) => provider,
+            None => {
+                assert!(!self.permissioned, "provider is not registered");
+                Provider::new()
+            }
+        };
+        provider.assert_active();
 
         let pair_name = format!("{}:{}", pair, env::predecessor_account_id());
         assert!(
             provider.pairs.get(&pair_name).is_none(),
             "pair already exists"
         );
-        provider.pairs.insert(
+        provider.set_entry(
             &pair_name,
-            &PriceEntry {
+            PriceEntry {
                 price: initial_price,
                 decimals,
                 last_update: env::block_timestamp(),
+                round_id: 0,
+                pusher: None,
+                config: config.unwrap_or_default(),
+                breaker: None,
+                pending: None,
+                commit_reveal: None,
             },
         );
+        provider.list_pair(&env::predecessor_account_id(), &pair);
+        provider.scores.insert(&pair_name, &PairScore::default());
 
-        self.providers
-            .insert(&env::predecessor_account_id(), &provider);
+        self.set_provider(&env::predecessor_account_id(), provider);
+        self.index_pair_provider(&pair, &env::predecessor_account_id());
+        if let Some(metadata) = metadata {
+            self.set_metadata(&pair, &env::predecessor_account_id(), metadata);
+        }
 
-        // check for storage deposit
-        let storage_cost =
-            env::storage_byte_cost() * u128::from(env::storage_usage() - initial_storage_usage);
+        FPOEvent::PairCreated(vec![PairCreatedData {
+            pair: &pair,
+            provider: &env::predecessor_account_id(),
+            price: initial_price,
+            decimals,
+            timestamp: env::block_timestamp(),
+        }])
+        .emit();
+
+        // check for storage deposit, including the round history filled by later pushes
+        let storage_usage = env::storage_usage() - initial_storage_usage
+            + dispute::round_history_storage_usage(&pair_name);
+        let storage_cost = env::storage_byte_cost() * u128::from(storage_usage);
         assert!(
             storage_cost <= env::attached_deposit(),
             "Insufficient storage, need {}",
@@ -55,14 +234,111 @@ impl FPOContract {
         );
     }
 
-    /// Sets the price for a given price pair by a provider
+    /// Sets the price for a given price pair by a provider, or by a pusher on behalf of `provider`
     #[payable]
-    pub fn push_data(&mut self, pair: String, price: U128) {
+    pub fn push_data(&mut self, pair: String, price: U128, provider: Option<AccountId>) {
+        self.assert_not_paused();
+        let pusher = env::predecessor_account_id();
+        let provider_id = provider.unwrap_or_else(|| pusher.clone());
+
+        let provider = self.get_pushing_provider(&pair, &provider_id, &pusher);
+        provider.assert_no_commit_reveal(&format!("{}:{}", pair, provider_id));
+        self.update_price(provider, &pair, price, &provider_id, &pusher);
+    }
+
+    /// Removes a price pair by a provider and refunds the released storage, which isn't possible
+    /// while the pair has an open dispute
+    pub fn remove_pair(&mut self, pair: String) {
+        self.assert_not_paused();
+        let pair_name = format!("{}:{}", pair, env::predecessor_account_id());
+        // the provider didn't pay for the storage of disputes, so it isn't refunded
+        self.remove_disputes(&pair_name);
+        let initial_storage_usage = env::storage_usage();
+
+        let mut provider = self.get_provider_expect(&env::predecessor_account_id());
+        let entry = provider
+            .pairs
+            .remove(&pair_name)
+            .map(PriceEntry::from)
+            .expect("pair does not exist");
+        self.remove_rounds(&pair_name, &entry);
+        provider.scores.remove(&pair_name);
+        provider.unlist_pair(&env::predecessor_account_id(), &pair);
+        self.set_provider(&env::predecessor_account_id(), provider);
+        self.unindex_pair_provider(&pair, &env::predecessor_account_id());
+        self.remove_metadata(&pair, &env::predecessor_account_id());
+
+        FPOEvent::PairRemoved(vec![PairRemovedData {
+            pair: &pair,
+            provider: &env::predecessor_account_id(),
+        }])
+        .emit();
+
+        let refund =
+            env::storage_byte_cost() * u128::from(initial_storage_usage - env::storage_usage());
+        if refund > 0 {
+            Promise::new(env::predecessor_account_id()).transfer(refund);
+        }
+    }
+
+    /// Sets the fee a provider charges for querying its prices
+    pub fn set_fee(&mut self, fee: U128) {
+        let mut provider = self.get_provider_expect(&env::predecessor_account_id());
+        provider.set_fee(fee.into());
+        self.set_provider(&env::predecessor_account_id(), provider);
+
+        FPOEvent::FeeUpdated(vec![FeeUpdatedData {
+            provider: &env::predecessor_account_id(),
+            fee,
+        }])
+        .emit();
+    }
+
+    /// Updates the heartbeat and deviation threshold of a price pair by a provider
+    pub fn set_pair_config(&mut self, pair: String, config: PairConfig) {
         let mut provider = self.get_provider_expect(&env::predecessor_account_id());
         let pair_name = format!("{}:{}", pair, env::predecessor_account_id());
-        provider.set_price(pair_name, price, env::block_timestamp());
-        self.providers
-            .insert(&env::predecessor_account_id(), &provider);
+        let mut entry = provider.get_entry_expect(&pair_name);
+        entry.config = config;
+        provider.set_entry(&pair_name, entry);
+        self.set_provider(&env::predecessor_account_id(), provider);
+    }
+
+    /// Checks if a price pair by a provider missed its heartbeat
+    pub fn is_stale(&self, pair: String, provider: AccountId) -> bool {
+        let pair_name = format!("{}:{}", pair, provider);
+        self.get_provider_expect(&provider)
+            .get_entry_expect(&pair_name)
+            .is_stale(env::block_timestamp())
+    }
+
+    /// Checks if a provider should push `price` for a pair, see `PriceEntry::needs_update()`
+    pub fn needs_update(&self, pair: String, provider: AccountId, price: U128) -> bool {
+        let pair_name = format!("{}:{}", pair, provider);
+        self.get_provider_expect(&provider)
+            .get_entry_expect(&pair_name)
+            .needs_update(price, env::block_timestamp())
+    }
+
+    /// Returns the pairs of a provider that missed their heartbeat
+    pub fn get_stale_pairs(&self, provider: AccountId) -> Vec<String> {
+        let provider_account_id = provider;
+        let provider = self.get_provider_expect(&provider_account_id);
+        provider
+            .get_pair_names(&provider_account_id)
+            .into_iter()
+            .filter(|pair| {
+                provider
+                    .get_entry_option(&format!("{}:{}", pair, provider_account_id))
+                    .map(|entry| entry.is_stale(env::block_timestamp()))
+                    .unwrap_or(false)
+            })
+            .collect()
+    }
+
+    /// Returns the fee a provider charges for querying its prices
+    pub fn get_fee(&self, provider: AccountId) -> U128 {
+        U128(self.get_provider_expect(&provider).query_fee)
     }
 
     /// Returns all data associated with a price pair by a provider
@@ -107,6 +383,25 @@ impl FPOContract {
         result
     }
 
+    /// Returns all the data associated with multiple price pairs by associated providers,
+    /// None for unknown providers and pairs
+    pub fn get_entries(
+        &self,
+        pairs: Vec<String>,
+        providers: Vec<AccountId>,
+    ) -> Vec<Option<PriceEntry>> {
+        assert_eq!(
+            pairs.len(),
+            providers.len(),
+            "pairs and provider should be of equal length"
+        );
+        pairs
+            .into_iter()
+            .zip(providers)
+            .map(|(pair, provider)| self.get_entry(pair, provider))
+            .collect()
+    }
+
     /// Checks if a given price pair exists
     pub fn pair_exists(&self, pair: String, provider: AccountId) -> bool {
         let pair_name = format!("{}:{}", pair, provider);
@@ -146,16 +441,18 @@ mod tests {
         builder
     }
 
-    #[should_panic]
+    #[should_panic(expected = "Insufficient storage")]
     #[test]
     fn pair_name_too_long() {
         let context = get_context(alice(), alice());
         testing_env!(context.build());
         let mut fpo_contract = FPOContract::new();
         fpo_contract.create_pair(
-            "1234567890123".to_string(),
+            "1234567/12345".to_string(),
             u16::max_value(),
             U128(u128::max_value()),
+            None,
+            None,
         );
     }
 
@@ -170,10 +467,32 @@ mod tests {
             "123456789012".to_string(),
             u16::max_value(),
             U128(u128::max_value()),
+            None,
+            None,
         );
 
         let storage_used_after = env::storage_usage();
-        assert_eq!(storage_used_after - storage_used_before, 170);
+        assert_eq!(storage_used_after - storage_used_before, 557);
+    }
+
+    #[test]
+    fn measure_storage_cost_of_routed_pair() {
+        let context = get_context(alice(), alice());
+        testing_env!(context.build());
+        let mut fpo_contract = FPOContract::new();
+
+        // pairs with a base and quote asset are also added to the routing index
+        let storage_used_before = env::storage_usage();
+        fpo_contract.create_pair(
+            "123456/12345".to_string(),
+            u16::max_value(),
+            U128(u128::max_value()),
+            None,
+            None,
+        );
+
+        let storage_used_after = env::storage_usage();
+        assert_eq!(storage_used_after - storage_used_before, 698);
     }
 
     #[test]
@@ -181,7 +500,7 @@ mod tests {
         let context = get_context(alice(), alice());
         testing_env!(context.build());
         let mut fpo_contract = FPOContract::new();
-        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2500));
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2500), None, None);
         assert_eq!(
             true,
             fpo_contract.pair_exists("ETH/USD".to_string(), env::predecessor_account_id())
@@ -193,13 +512,13 @@ mod tests {
         let context = get_context(alice(), alice());
         testing_env!(context.build());
         let mut fpo_contract = FPOContract::new();
-        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2500));
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2500), None, None);
         assert_eq!(
             true,
             fpo_contract.pair_exists("ETH/USD".to_string(), env::predecessor_account_id())
         );
 
-        fpo_contract.create_pair("BTC/USD".to_string(), 8, U128(42000));
+        fpo_contract.create_pair("BTC/USD".to_string(), 8, U128(42000), None, None);
         assert_eq!(
             true,
             fpo_contract.pair_exists("BTC/USD".to_string(), env::predecessor_account_id())
@@ -218,19 +537,41 @@ mod tests {
         );
     }
 
+    #[test]
+    fn get_entries() {
+        testing_env!(get_context(alice(), alice()).build());
+        let mut fpo_contract = FPOContract::new();
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2500), None, None);
+
+        let entries = fpo_contract.get_entries(
+            vec![
+                "ETH/USD".to_string(),
+                "BTC/USD".to_string(),
+                "ETH/USD".to_string(),
+            ],
+            vec![alice(), alice(), bob()],
+        );
+        let entry = entries[0].as_ref().unwrap();
+        assert_eq!(U128(2500), entry.price);
+        assert_eq!(8, entry.decimals);
+        assert_eq!(env::block_timestamp(), entry.last_update);
+        assert!(entries[1].is_none());
+        assert!(entries[2].is_none());
+    }
+
     #[test]
     #[should_panic]
     fn create_same_pair() {
         let context = get_context(alice(), alice());
         testing_env!(context.build());
         let mut fpo_contract = FPOContract::new();
-        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2500));
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2500), None, None);
         assert_eq!(
             true,
             fpo_contract.pair_exists("ETH/USD".to_string(), env::predecessor_account_id())
         );
 
-        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2500));
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2500), None, None);
     }
 
     #[test]
@@ -238,7 +579,7 @@ mod tests {
         let context = get_context(alice(), alice());
         testing_env!(context.build());
         let mut fpo_contract = FPOContract::new();
-        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2500));
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2500), None, None);
         assert_eq!(
             U128(2500),
             fpo_contract
@@ -247,7 +588,7 @@ mod tests {
                 .price
         );
 
-        fpo_contract.push_data("ETH/USD".to_string(), U128(3000));
+        fpo_contract.push_data("ETH/USD".to_string(), U128(3000), None);
 
         assert_eq!(
             U128(3000),
@@ -264,7 +605,7 @@ mod tests {
         testing_env!(context.build());
 
         let mut fpo_contract = FPOContract::new();
-        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2500));
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2500), None, None);
         assert_eq!(
             U128(2500),
             fpo_contract
@@ -277,7 +618,7 @@ mod tests {
         context = get_context(bob(), bob());
         testing_env!(context.build());
 
-        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2700));
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2700), None, None);
         assert_eq!(
             U128(2700),
             fpo_contract
@@ -293,7 +634,7 @@ mod tests {
                 .price
         );
 
-        fpo_contract.push_data("ETH/USD".to_string(), U128(3000));
+        fpo_contract.push_data("ETH/USD".to_string(), U128(3000), None);
 
         assert_eq!(
             U128(3000),
@@ -303,4 +644,147 @@ mod tests {
                 .price
         );
     }
+
+    #[test]
+    fn heartbeat_marks_pair_stale() {
+        let mut context = get_context(alice(), alice());
+        testing_env!(context.block_timestamp(100).build());
+        let mut fpo_contract = FPOContract::new();
+        fpo_contract.create_pair(
+            "ETH/USD".to_string(),
+            8,
+            U128(2500),
+            Some(PairConfig {
+                heartbeat: Some(60),
+                deviation_threshold: Some(50),
+            }),
+            None,
+        );
+        fpo_contract.create_pair("BTC/USD".to_string(), 8, U128(42000), None, None);
+        assert!(!fpo_contract.is_stale("ETH/USD".to_string(), alice()));
+
+        testing_env!(context.block_timestamp(161).build());
+        assert!(fpo_contract.is_stale("ETH/USD".to_string(), alice()));
+        // pairs without a heartbeat never go stale
+        assert!(!fpo_contract.is_stale("BTC/USD".to_string(), alice()));
+        assert_eq!(
+            vec!["ETH/USD".to_string()],
+            fpo_contract.get_stale_pairs(alice())
+        );
+
+        fpo_contract.push_data("ETH/USD".to_string(), U128(3000), None);
+        assert!(!fpo_contract.is_stale("ETH/USD".to_string(), alice()));
+        assert!(fpo_contract.get_stale_pairs(alice()).is_empty());
+    }
+
+    #[test]
+    fn deviation_threshold_warrants_update() {
+        let mut context = get_context(alice(), alice());
+        testing_env!(context.block_timestamp(100).build());
+        let mut fpo_contract = FPOContract::new();
+        fpo_contract.create_pair(
+            "ETH/USD".to_string(),
+            8,
+            U128(2000),
+            Some(PairConfig {
+                heartbeat: Some(60),
+                deviation_threshold: Some(50), // 0.5%
+            }),
+            None,
+        );
+        fpo_contract.create_pair("BTC/USD".to_string(), 8, U128(40000), None, None);
+
+        let needs_update = |fpo_contract: &FPOContract, pair: &str, price: u128| {
+            fpo_contract.needs_update(pair.to_string(), alice(), U128(price))
+        };
+        assert!(!needs_update(&fpo_contract, "ETH/USD", 2009));
+        assert!(needs_update(&fpo_contract, "ETH/USD", 2010));
+        assert!(needs_update(&fpo_contract, "ETH/USD", 1990));
+        // without a threshold any change warrants an update
+        assert!(!needs_update(&fpo_contract, "BTC/USD", 40000));
+        assert!(needs_update(&fpo_contract, "BTC/USD", 40001));
+
+        // a stale pair needs an update even if the price didn't move
+        testing_env!(context.block_timestamp(161).build());
+        assert!(needs_update(&fpo_contract, "ETH/USD", 2000));
+    }
+
+    #[test]
+    fn set_pair_config() {
+        let mut context = get_context(alice(), alice());
+        testing_env!(context.block_timestamp(100).build());
+        let mut fpo_contract = FPOContract::new();
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2500), None, None);
+        assert_eq!(
+            PairConfig::default(),
+            fpo_contract
+                .get_entry("ETH/USD".to_string(), alice())
+                .unwrap()
+                .config
+        );
+
+        let config = PairConfig {
+            heartbeat: Some(10),
+            deviation_threshold: None,
+        };
+        fpo_contract.set_pair_config("ETH/USD".to_string(), config.clone());
+        assert_eq!(
+            config,
+            fpo_contract
+                .get_entry("ETH/USD".to_string(), alice())
+                .unwrap()
+                .config
+        );
+
+        testing_env!(context.block_timestamp(111).build());
+        assert!(fpo_contract.is_stale("ETH/USD".to_string(), alice()));
+    }
+
+    #[test]
+    fn remove_pair_unlists_stale_pair() {
+        let mut context = get_context(alice(), alice());
+        testing_env!(context.block_timestamp(100).build());
+        let mut fpo_contract = FPOContract::new();
+        fpo_contract.create_pair(
+            "ETH/USD".to_string(),
+            8,
+            U128(2500),
+            Some(PairConfig {
+                heartbeat: Some(60),
+                deviation_threshold: None,
+            }),
+            None,
+        );
+
+        testing_env!(context.block_timestamp(200).build());
+        fpo_contract.remove_pair("ETH/USD".to_string());
+        assert!(fpo_contract.get_stale_pairs(alice()).is_empty());
+    }
+
+    #[test]
+    fn remove_pair_keeps_pair_list() {
+        let context = get_context(alice(), alice());
+        testing_env!(context.build());
+        let mut fpo_contract = FPOContract::new();
+        for pair in ["ETH/USD", "BTC/USD", "SOL/USD"].iter() {
+            fpo_contract.create_pair(pair.to_string(), 8, U128(2500), None, None);
+        }
+        fpo_contract.remove_pair("ETH/USD".to_string());
+
+        // the last pair takes the place of the removed one
+        let provider = fpo_contract.get_provider_expect(&alice());
+        assert_eq!(
+            vec!["SOL/USD".to_string(), "BTC/USD".to_string()],
+            provider.get_pair_names(&alice())
+        );
+
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2500), None, None);
+        fpo_contract.remove_pair("ETH/USD".to_string());
+        assert_eq!(
+            vec!["SOL/USD".to_string(), "BTC/USD".to_string()],
+            fpo_contract
+                .get_provider_expect(&alice())
+                .get_pair_names(&alice())
+        );
+    }
 }
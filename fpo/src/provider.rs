@@ -1,15 +1,36 @@
 use crate::*;
+use bond::Unbonding;
+use breaker::{PendingPrice, PriceUpdate};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::PublicKey;
 use price_pair::PriceEntry;
+use pusher::Pusher;
+use reputation::PairScore;
+use upgrade::VersionedPriceEntry;
+
+#[derive(
+    BorshDeserialize, BorshSerialize, Deserialize, Serialize, Debug, Clone, Copy, PartialEq,
+)]
+pub enum ProviderStatus {
+    Active,    // Can create pairs and push data
+    Suspended, // Temporarily barred from pushing data
+    Retired,   // Removed from the registry
+}
 
 #[derive(BorshDeserialize, BorshSerialize)]
 pub struct Provider {
     pub query_fee: u128,
-    pub pairs: LookupMap<String, PriceEntry>, // Maps "{TICKER_1}/{TICKER_2}-{PROVIDER}" => PriceEntry - e.g.: ETHUSD => PriceEntry
-}
-
-#[derive(BorshStorageKey, BorshSerialize)]
-pub enum ProviderStorageKeys {
-    Pairs,
+    pub pairs: LookupMap<String, VersionedPriceEntry>, // Maps "{TICKER_1}/{TICKER_2}-{PROVIDER}" => PriceEntry - e.g.: ETHUSD => PriceEntry
+    pub status: ProviderStatus,
+    pub pushers: LookupMap<String, Pusher>, // Maps "{PUSHER}:{PROVIDER}" => Pusher
+    pub pair_count: u32,                    // Number of pairs in `pair_list`
+    pub pair_list: LookupMap<String, String>, // Maps "{PROVIDER}:{INDEX}" => "{TICKER_1}/{TICKER_2}"
+    pub pair_indices: LookupMap<String, u32>, // Maps "{TICKER_1}/{TICKER_2}:{PROVIDER}" => index in `pair_list`
+    pub scores: LookupMap<String, PairScore>, // Maps "{TICKER_1}/{TICKER_2}:{PROVIDER}" => PairScore
+    pub bond: u128,                           // Stake that can be slashed for bad prices
+    pub unbonding: Option<Unbonding>,         // Stake waiting to be withdrawn
+    pub signing_key: Option<PublicKey>,       // Key that signs price reports
+    pub report_nonce: u64,                    // Nonce of the last signed report
 }
 
 /// Provider methods (internal)
@@ -17,20 +38,90 @@ impl Provider {
     pub fn new() -> Self {
         Self {
             query_fee: 0,
-            pairs: LookupMap::new(ProviderStorageKeys::Pairs),
+            pairs: LookupMap::new(FPOStorageKeys::ProviderPairs),
+            status: ProviderStatus::Active,
+            pushers: LookupMap::new(FPOStorageKeys::Pushers),
+            pair_count: 0,
+            pair_list: LookupMap::new(FPOStorageKeys::PairList),
+            pair_indices: LookupMap::new(FPOStorageKeys::PairIndices),
+            scores: LookupMap::new(FPOStorageKeys::Scores),
+            bond: 0,
+            unbonding: None,
+            signing_key: None,
+            report_nonce: 0,
         }
     }
 
+    /// Panics if the provider is suspended or retired
+    pub fn assert_active(&self) {
+        assert_eq!(
+            self.status,
+            ProviderStatus::Active,
+            "provider is not active"
+        );
+    }
+
     /// Returns all data associated with a price pair
     pub fn get_entry_expect(&self, pair: &str) -> PriceEntry {
         self.pairs
             .get(&pair.to_string())
+            .map(PriceEntry::from)
             .unwrap_or_else(|| panic!("no price available for {}", pair))
     }
 
     /// Returns all data associated with a price pair, returning None if no price is available
     pub fn get_entry_option(&self, pair: &str) -> Option<PriceEntry> {
-        self.pairs.get(&pair.to_string())
+        self.pairs.get(&pair.to_string()).map(PriceEntry::from)
+    }
+
+    /// Stores the data associated with a price pair in the latest layout
+    pub fn set_entry(&mut self, pair: &str, entry: PriceEntry) {
+        self.pairs
+            .insert(&pair.to_string(), &VersionedPriceEntry::from(entry));
+    }
+
+    /// Adds a pair to the list of pairs of the provider, returns false if it was listed already
+    pub fn list_pair(&mut self, account_id: &AccountId, pair: &str) -> bool {
+        let pair_name = format!("{}:{}", pair, account_id);
+        if self.pair_indices.get(&pair_name).is_some() {
+            return false;
+        }
+        self.pair_indices.insert(&pair_name, &self.pair_count);
+        self.pair_list.insert(
+            &format!("{}:{}", account_id, self.pair_count),
+            &pair.to_string(),
+        );
+        self.pair_count += 1;
+        true
+    }
+
+    /// Removes a pair from the list of pairs of the provider, moving the last pair into its place
+    pub fn unlist_pair(&mut self, account_id: &AccountId, pair: &str) {
+        let index = match self
+            .pair_indices
+            .remove(&format!("{}:{}", pair, account_id))
+        {
+            Some(index) => index,
+            None => return,
+        };
+        self.pair_count -= 1;
+        let last = self
+            .pair_list
+            .remove(&format!("{}:{}", account_id, self.pair_count))
+            .expect("pair list is corrupted");
+        if index != self.pair_count {
+            self.pair_indices
+                .insert(&format!("{}:{}", last, account_id), &index);
+            self.pair_list
+                .insert(&format!("{}:{}", account_id, index), &last);
+        }
+    }
+
+    /// Returns the pairs of the provider, e.g. ["ETH/USD", "BTC/USD"]
+    pub fn get_pair_names(&self, account_id: &AccountId) -> Vec<String> {
+        (0..self.pair_count)
+            .filter_map(|index| self.pair_list.get(&format!("{}:{}", account_id, index)))
+            .collect()
     }
 
     /// Sets the fee for querying prices (not yet implemented)
@@ -38,13 +129,57 @@ impl Provider {
         self.query_fee = fee
     }
 
-    /// Sets the answer for a given price pair by a provider
-    pub fn set_price(&mut self, pair: String, price: U128, updated: u64) {
-        let mut entry = self.pairs.get(&pair).expect("pair does not exist");
+    /// Checks if a pusher may push data for a price pair on behalf of the provider
+    pub fn can_push(&self, pusher_key: &str, pair: &str) -> bool {
+        match self.pushers.get(&pusher_key.to_string()) {
+            Some(pusher) => pusher
+                .pairs
+                .map(|pairs| pairs.iter().any(|p| p == pair))
+                .unwrap_or(true),
+            None => false,
+        }
+    }
+
+    /// Sets the answer for a given price pair by a provider unless the circuit breaker holds it back
+    pub fn set_price(
+        &mut self,
+        pair: String,
+        price: U128,
+        updated: u64,
+        pusher: AccountId,
+    ) -> PriceUpdate {
+        let mut entry = self.get_entry_option(&pair).expect("pair does not exist");
+
+        if let Some(breaker) = &entry.breaker {
+            breaker.assert_within_bounds(price);
+            let confirmed = entry
+                .pending
+                .as_ref()
+                .map(|pending| breaker.confirms(pending, price, updated))
+                .unwrap_or(false);
+            if breaker.exceeds_deviation(entry.price, price) && !confirmed {
+                assert!(
+                    breaker.confirmation_window.is_some(),
+                    "price deviates too much from the last price"
+                );
+                entry.pending = Some(PendingPrice {
+                    price,
+                    pushed_at: updated,
+                    pusher,
+                });
+                self.set_entry(&pair, entry.clone());
+                return PriceUpdate::Pending(entry);
+            }
+        }
+
         entry.last_update = updated;
         entry.price = price;
+        entry.round_id += 1;
+        entry.pusher = Some(pusher);
+        entry.pending = None;
 
-        self.pairs.insert(&pair, &entry);
+        self.set_entry(&pair, entry.clone());
+        PriceUpdate::Accepted(entry)
     }
 }
 
@@ -60,11 +195,18 @@ impl FPOContract {
     pub fn get_provider_expect(&self, account_id: &AccountId) -> Provider {
         self.providers
             .get(account_id)
+            .map(Provider::from)
             .expect("no provider with this account id")
     }
 
     /// Returns all the data associated with a provider wrapped in an Option
     pub fn get_provider_option(&self, account_id: &AccountId) -> Option<Provider> {
-        self.providers.get(account_id)
+        self.providers.get(account_id).map(Provider::from)
+    }
+
+    /// Stores a provider in the latest layout
+    pub fn set_provider(&mut self, account_id: &AccountId, provider: Provider) {
+        self.providers
+            .insert(account_id, &VersionedProvider::from(provider));
     }
 }
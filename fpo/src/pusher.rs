@@ -0,0 +1,172 @@
+use crate::*;
+use near_sdk::serde::{Deserialize, Serialize};
+
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Pusher {
+    pub pairs: Option<Vec<String>>, // Pairs the pusher may update, all pairs of the provider if None
+}
+
+/// Public contract methods
+#[near_bindgen]
+impl FPOContract {
+    /// Allows an account to push data on behalf of the calling provider, optionally only for some pairs
+    #[payable]
+    pub fn add_pusher(&mut self, pusher: AccountId, pairs: Option<Vec<String>>) {
+        let initial_storage_usage = env::storage_usage();
+
+        let mut provider = self.get_provider_expect(&env::predecessor_account_id());
+        let pusher_key = format!("{}:{}", pusher, env::predecessor_account_id());
+        provider.pushers.insert(&pusher_key, &Pusher { pairs });
+        self.set_provider(&env::predecessor_account_id(), provider);
+
+        // check for storage deposit
+        let storage_cost = env::storage_byte_cost()
+            * u128::from(env::storage_usage().saturating_sub(initial_storage_usage));
+        assert!(
+            storage_cost <= env::attached_deposit(),
+            "Insufficient storage, need {}",
+            storage_cost
+        );
+    }
+
+    /// Revokes the permission of an account to push data on behalf of the calling provider
+    pub fn remove_pusher(&mut self, pusher: AccountId) {
+        let mut provider = self.get_provider_expect(&env::predecessor_account_id());
+        let pusher_key = format!("{}:{}", pusher, env::predecessor_account_id());
+        assert!(
+            provider.pushers.remove(&pusher_key).is_some(),
+            "pusher does not exist"
+        );
+        self.set_provider(&env::predecessor_account_id(), provider);
+    }
+
+    /// Returns the permissions of a pusher for a provider
+    pub fn get_pusher(&self, provider: AccountId, pusher: AccountId) -> Option<Pusher> {
+        let pusher_key = format!("{}:{}", pusher, provider);
+        self.get_provider_option(&provider)
+            .and_then(|provider| provider.pushers.get(&pusher_key))
+    }
+}
+
+/// Pusher tests
+#[cfg(test)]
+mod tests {
+
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+    use price_pair::STORAGE_COST;
+
+    use super::*;
+
+    fn alice() -> AccountId {
+        "alice.near".parse().unwrap()
+    }
+    fn bob() -> AccountId {
+        "bob.near".parse().unwrap()
+    }
+    fn carol() -> AccountId {
+        "carol.near".parse().unwrap()
+    }
+
+    fn get_context(
+        predecessor_account_id: AccountId,
+        current_account_id: AccountId,
+    ) -> VMContextBuilder {
+        let mut builder = VMContextBuilder::new();
+        builder
+            .current_account_id(current_account_id.clone())
+            .signer_account_id("robert.testnet".parse().unwrap())
+            .predecessor_account_id(predecessor_account_id.clone())
+            .attached_deposit(STORAGE_COST);
+        builder
+    }
+
+    /// Creates a contract where alice provides ETH/USD and BTC/USD
+    fn setup() -> FPOContract {
+        testing_env!(get_context(alice(), alice()).build());
+        let mut fpo_contract = FPOContract::new();
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2500), None, None);
+        fpo_contract.create_pair("BTC/USD".to_string(), 8, U128(40000), None, None);
+        fpo_contract
+    }
+
+    #[test]
+    fn pusher_pushes_for_provider() {
+        let mut fpo_contract = setup();
+        fpo_contract.add_pusher(bob(), None);
+        assert_eq!(
+            Some(Pusher { pairs: None }),
+            fpo_contract.get_pusher(alice(), bob())
+        );
+
+        testing_env!(get_context(bob(), alice()).build());
+        fpo_contract.push_data("ETH/USD".to_string(), U128(3000), Some(alice()));
+        fpo_contract.push_data("BTC/USD".to_string(), U128(45000), Some(alice()));
+
+        let entry = fpo_contract
+            .get_entry("ETH/USD".to_string(), alice())
+            .unwrap();
+        assert_eq!(U128(3000), entry.price);
+        assert_eq!(Some(bob()), entry.pusher);
+        assert_eq!(
+            Some(U128(45000)),
+            fpo_contract.get_price("BTC/USD".to_string(), &alice())
+        );
+    }
+
+    #[test]
+    fn scoped_pusher_pushes_allowed_pair() {
+        let mut fpo_contract = setup();
+        fpo_contract.add_pusher(bob(), Some(vec!["ETH/USD".to_string()]));
+
+        testing_env!(get_context(bob(), alice()).build());
+        fpo_contract.push_data("ETH/USD".to_string(), U128(3000), Some(alice()));
+        assert_eq!(
+            Some(U128(3000)),
+            fpo_contract.get_price("ETH/USD".to_string(), &alice())
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "not allowed to push data for this provider")]
+    fn scoped_pusher_pushes_other_pair() {
+        let mut fpo_contract = setup();
+        fpo_contract.add_pusher(bob(), Some(vec!["ETH/USD".to_string()]));
+
+        testing_env!(get_context(bob(), alice()).build());
+        fpo_contract.push_data("BTC/USD".to_string(), U128(45000), Some(alice()));
+    }
+
+    #[test]
+    #[should_panic(expected = "not allowed to push data for this provider")]
+    fn unknown_pusher_pushes() {
+        let mut fpo_contract = setup();
+        fpo_contract.add_pusher(bob(), None);
+
+        testing_env!(get_context(carol(), alice()).build());
+        fpo_contract.push_data("ETH/USD".to_string(), U128(3000), Some(alice()));
+    }
+
+    #[test]
+    #[should_panic(expected = "not allowed to push data for this provider")]
+    fn removed_pusher_pushes() {
+        let mut fpo_contract = setup();
+        fpo_contract.add_pusher(bob(), None);
+        fpo_contract.remove_pusher(bob());
+        assert_eq!(None, fpo_contract.get_pusher(alice(), bob()));
+
+        testing_env!(get_context(bob(), alice()).build());
+        fpo_contract.push_data("ETH/USD".to_string(), U128(3000), Some(alice()));
+    }
+
+    #[test]
+    fn provider_pushes_own_pair() {
+        let mut fpo_contract = setup();
+        fpo_contract.push_data("ETH/USD".to_string(), U128(3000), Some(alice()));
+        let entry = fpo_contract
+            .get_entry("ETH/USD".to_string(), alice())
+            .unwrap();
+        assert_eq!(U128(3000), entry.price);
+        assert_eq!(Some(alice()), entry.pusher);
+    }
+}
@@ -0,0 +1,244 @@
+use crate::*;
+use admin::Role;
+use events::{FPOEvent, ProviderStatusUpdatedData};
+use provider::ProviderStatus;
+
+/// Private contract methods
+impl FPOContract {
+    /// Updates the status of a provider and emits a `provider_status_updated` event
+    fn update_provider_status(
+        &mut self,
+        account_id: &AccountId,
+        mut provider: Provider,
+        status: ProviderStatus,
+    ) {
+        provider.status = status;
+        self.set_provider(account_id, provider);
+
+        FPOEvent::ProviderStatusUpdated(vec![ProviderStatusUpdatedData {
+            provider: account_id,
+            status,
+        }])
+        .emit();
+    }
+}
+
+/// Public contract methods
+#[near_bindgen]
+impl FPOContract {
+    /// Enables or disables permissioned mode, in which only registered providers can create pairs
+    pub fn set_permissioned(&mut self, permissioned: bool) {
+        self.assert_role(Role::Admin);
+        self.permissioned = permissioned;
+    }
+
+    /// Checks if the contract is in permissioned mode
+    pub fn is_permissioned(&self) -> bool {
+        self.permissioned
+    }
+
+    /// Registers a provider or reactivates a retired one
+    pub fn register_provider(&mut self, account_id: AccountId) {
+        self.assert_role(Role::ProviderManager);
+        let provider = self
+            .get_provider_option(&account_id)
+            .unwrap_or_else(Provider::new);
+        self.update_provider_status(&account_id, provider, ProviderStatus::Active);
+    }
+
+    /// Retires a provider, keeping its pairs readable
+    pub fn remove_provider(&mut self, account_id: AccountId) {
+        self.assert_role(Role::ProviderManager);
+        let provider = self.get_provider_expect(&account_id);
+        self.update_provider_status(&account_id, provider, ProviderStatus::Retired);
+    }
+
+    /// Sets the status of a provider, e.g. to suspend it
+    pub fn set_provider_status(&mut self, account_id: AccountId, status: ProviderStatus) {
+        self.assert_role(Role::ProviderManager);
+        let provider = self.get_provider_expect(&account_id);
+        self.update_provider_status(&account_id, provider, status);
+    }
+
+    /// Returns the status of a provider
+    pub fn get_provider_status(&self, account_id: AccountId) -> Option<ProviderStatus> {
+        self.get_provider_option(&account_id)
+            .map(|provider| provider.status)
+    }
+}
+
+/// Provider registry tests
+#[cfg(test)]
+mod tests {
+
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+    use price_pair::STORAGE_COST;
+
+    use super::*;
+    use aggregate::AggregateOptions;
+
+    fn alice() -> AccountId {
+        "alice.near".parse().unwrap()
+    }
+    fn bob() -> AccountId {
+        "bob.near".parse().unwrap()
+    }
+    fn carol() -> AccountId {
+        "carol.near".parse().unwrap()
+    }
+
+    fn get_context(
+        predecessor_account_id: AccountId,
+        current_account_id: AccountId,
+    ) -> VMContextBuilder {
+        let mut builder = VMContextBuilder::new();
+        builder
+            .current_account_id(current_account_id.clone())
+            .signer_account_id("robert.testnet".parse().unwrap())
+            .predecessor_account_id(predecessor_account_id.clone())
+            .attached_deposit(STORAGE_COST);
+        builder
+    }
+
+    /// Creates a permissioned contract owned by alice
+    fn setup() -> FPOContract {
+        testing_env!(get_context(alice(), alice()).build());
+        let mut fpo_contract = FPOContract::new();
+        fpo_contract.set_permissioned(true);
+        fpo_contract
+    }
+
+    #[test]
+    fn registered_provider_creates_pair() {
+        let mut fpo_contract = setup();
+        fpo_contract.register_provider(bob());
+        assert_eq!(
+            Some(ProviderStatus::Active),
+            fpo_contract.get_provider_status(bob())
+        );
+
+        testing_env!(get_context(bob(), alice()).build());
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2500), None, None);
+        fpo_contract.push_data("ETH/USD".to_string(), U128(3000), None);
+        assert_eq!(
+            Some(U128(3000)),
+            fpo_contract.get_price("ETH/USD".to_string(), &bob())
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "provider is not registered")]
+    fn unregistered_provider_creates_pair() {
+        let mut fpo_contract = setup();
+        testing_env!(get_context(bob(), alice()).build());
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2500), None, None);
+    }
+
+    #[test]
+    fn permissionless_provider_creates_pair() {
+        let mut fpo_contract = setup();
+        fpo_contract.set_permissioned(false);
+        testing_env!(get_context(bob(), alice()).build());
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2500), None, None);
+        assert_eq!(
+            Some(ProviderStatus::Active),
+            fpo_contract.get_provider_status(bob())
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "provider is not active")]
+    fn suspended_provider_pushes_data() {
+        let mut fpo_contract = setup();
+        fpo_contract.register_provider(bob());
+        testing_env!(get_context(bob(), alice()).build());
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2500), None, None);
+
+        testing_env!(get_context(alice(), alice()).build());
+        fpo_contract.set_provider_status(bob(), ProviderStatus::Suspended);
+
+        testing_env!(get_context(bob(), alice()).build());
+        fpo_contract.push_data("ETH/USD".to_string(), U128(3000), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "provider is not active")]
+    fn retired_provider_creates_pair() {
+        let mut fpo_contract = setup();
+        fpo_contract.register_provider(bob());
+        fpo_contract.remove_provider(bob());
+        assert_eq!(
+            Some(ProviderStatus::Retired),
+            fpo_contract.get_provider_status(bob())
+        );
+
+        testing_env!(get_context(bob(), alice()).build());
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2500), None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "missing role ProviderManager")]
+    fn register_provider_without_role() {
+        let mut fpo_contract = setup();
+        testing_env!(get_context(bob(), alice()).build());
+        fpo_contract.register_provider(bob());
+    }
+
+    #[test]
+    fn provider_manager_registers_provider() {
+        let mut fpo_contract = setup();
+        fpo_contract.grant_role(carol(), Role::ProviderManager);
+        testing_env!(get_context(carol(), alice()).build());
+        fpo_contract.register_provider(bob());
+        assert_eq!(
+            Some(ProviderStatus::Active),
+            fpo_contract.get_provider_status(bob())
+        );
+    }
+
+    #[test]
+    fn aggregate_active_only() {
+        let mut fpo_contract = setup();
+        fpo_contract.register_provider(bob());
+        fpo_contract.register_provider(carol());
+
+        testing_env!(get_context(bob(), alice()).build());
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2000), None, None);
+        testing_env!(get_context(carol(), alice()).build());
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(4000), None, None);
+
+        testing_env!(get_context(alice(), alice()).build());
+        fpo_contract.set_provider_status(carol(), ProviderStatus::Suspended);
+
+        let pairs = vec!["ETH/USD".to_string(), "ETH/USD".to_string()];
+        assert_eq!(
+            Some(U128(3000)),
+            fpo_contract.aggregate_avg(pairs.clone(), vec![bob(), carol()], 0, None)
+        );
+        assert_eq!(
+            Some(U128(2000)),
+            fpo_contract.aggregate_avg(
+                pairs.clone(),
+                vec![bob(), carol()],
+                0,
+                Some(AggregateOptions {
+                    active_only: true,
+                    ..Default::default()
+                })
+            )
+        );
+        assert_eq!(
+            vec![Some(U128(2000)), None],
+            fpo_contract.aggregate_collect(
+                pairs,
+                vec![bob(), carol()],
+                0,
+                Some(AggregateOptions {
+                    active_only: true,
+                    ..Default::default()
+                })
+            )
+        );
+    }
+}
@@ -0,0 +1,323 @@
+use crate::*;
+use aggregate::AggregateOptions;
+use near_sdk::serde::{Deserialize, Serialize};
+use price_pair::PriceEntry;
+use std::convert::TryFrom;
+
+/// Weight of the newest deviation in the rolling average, as 1 / SCORE_SMOOTHING
+const SCORE_SMOOTHING: u64 = 20;
+/// Score of a provider without any recorded issues
+pub const MAX_SCORE: u16 = 10_000;
+/// Maximum number of other providers an update is compared against, keeps pushes within the gas limit
+const MAX_CONSENSUS_PROVIDERS: usize = 16;
+
+/// Rolling statistics of the updates of a provider for a pair
+#[derive(
+    BorshDeserialize, BorshSerialize, Deserialize, Serialize, Debug, Clone, Default, PartialEq,
+)]
+pub struct PairScore {
+    pub updates: u64,           // Updates compared against other providers
+    pub avg_deviation_bps: u64, // Exponential moving average of the deviation from the median
+    pub stale_incidents: u64,   // Updates that arrived after the heartbeat expired
+    pub total_updates: u64,     // All accepted updates
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ProviderScore {
+    pub stats: PairScore,
+    pub score: u16, // From 0 to MAX_SCORE, higher is better
+}
+
+/// Score methods (internal)
+impl PairScore {
+    /// Records an accepted update with its deviation from the median of other providers
+    pub fn record(&mut self, deviation_bps: Option<u128>, was_stale: bool) {
+        self.total_updates += 1;
+        if was_stale {
+            self.stale_incidents += 1;
+        }
+        if let Some(deviation_bps) = deviation_bps {
+            let deviation_bps = u64::try_from(deviation_bps).unwrap_or(u64::MAX);
+            self.avg_deviation_bps = if self.updates == 0 {
+                deviation_bps
+            } else {
+                // u128 intermediates keep the weighted sum from overflowing
+                ((u128::from(self.avg_deviation_bps) * u128::from(SCORE_SMOOTHING - 1)
+                    + u128::from(deviation_bps))
+                    / u128::from(SCORE_SMOOTHING)) as u64
+            };
+            self.updates += 1;
+        }
+    }
+
+    /// Combines the average deviation and the share of stale updates into a single score
+    pub fn score(&self) -> u16 {
+        let max = u64::from(MAX_SCORE);
+        let deviation_penalty = self.avg_deviation_bps.min(max);
+        let stale_penalty = (self.stale_incidents * max)
+            .checked_div(self.total_updates)
+            .unwrap_or(0)
+            .min(max);
+        ((max - deviation_penalty) * (max - stale_penalty) / max) as u16
+    }
+}
+
+/// Reputation methods (internal)
+impl Provider {
+    /// Returns the score for a pair, the maximum if no statistics were recorded
+    pub fn get_score(&self, pair_name: &str) -> u16 {
+        self.scores
+            .get(&pair_name.to_string())
+            .map(|stats| stats.score())
+            .unwrap_or(MAX_SCORE)
+    }
+
+    /// Records an accepted update in the statistics of a pair
+    pub fn record_score(&mut self, pair_name: &str, deviation_bps: Option<u128>, was_stale: bool) {
+        let pair_name = pair_name.to_string();
+        let mut stats = self.scores.get(&pair_name).unwrap_or_default();
+        stats.record(deviation_bps, was_stale);
+        self.scores.insert(&pair_name, &stats);
+    }
+}
+
+/// Private contract methods
+impl FPOContract {
+    /// Returns the median price of a pair in `decimals` across the first `MAX_CONSENSUS_PROVIDERS`
+    /// providers of the pair other than `exclude`, leaving out inactive providers and entries
+    /// that missed their heartbeat
+    pub fn consensus_price(&self, pair: &str, exclude: &AccountId, decimals: u16) -> Option<u128> {
+        let providers: Vec<AccountId> = self
+            .pair_providers
+            .get(&pair.to_string())
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|account_id| account_id != exclude)
+            .take(MAX_CONSENSUS_PROVIDERS)
+            .collect();
+        let entries: Vec<(PriceEntry, u128)> = self
+            .collect_entries(
+                &vec![pair.to_string(); providers.len()],
+                &providers,
+                0,
+                &AggregateOptions {
+                    active_only: true,
+                    use_heartbeat: true,
+                    ..Default::default()
+                },
+            )
+            .into_iter()
+            .flatten()
+            .collect();
+        let mut prices = aggregate::rescale_prices(&entries, decimals);
+        aggregate::median_price(&mut prices, false).map(|price| price.0)
+    }
+}
+
+/// Public contract methods
+#[near_bindgen]
+impl FPOContract {
+    /// Returns how closely a provider's updates for a pair follow the other providers
+    pub fn get_provider_score(&self, provider: AccountId, pair: String) -> Option<ProviderScore> {
+        let pair_name = format!("{}:{}", pair, provider);
+        self.get_provider_option(&provider)
+            .and_then(|provider| provider.scores.get(&pair_name))
+            .map(|stats| ProviderScore {
+                score: stats.score(),
+                stats,
+            })
+    }
+}
+
+/// Reputation tests
+#[cfg(test)]
+mod tests {
+
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+    use price_pair::{PairConfig, STORAGE_COST};
+
+    use super::*;
+
+    fn alice() -> AccountId {
+        "alice.near".parse().unwrap()
+    }
+    fn bob() -> AccountId {
+        "bob.near".parse().unwrap()
+    }
+    fn carol() -> AccountId {
+        "carol.near".parse().unwrap()
+    }
+
+    fn get_context(
+        predecessor_account_id: AccountId,
+        current_account_id: AccountId,
+    ) -> VMContextBuilder {
+        let mut builder = VMContextBuilder::new();
+        builder
+            .current_account_id(current_account_id.clone())
+            .signer_account_id("robert.testnet".parse().unwrap())
+            .predecessor_account_id(predecessor_account_id.clone())
+            .attached_deposit(STORAGE_COST);
+        builder
+    }
+
+    /// Creates a contract where alice, bob and carol provide ETH/USD at 2000
+    fn setup() -> FPOContract {
+        testing_env!(get_context(alice(), alice()).build());
+        let mut fpo_contract = FPOContract::new();
+        for account_id in [alice(), bob(), carol()].iter() {
+            testing_env!(get_context(account_id.clone(), alice()).build());
+            fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2000), None, None);
+        }
+        fpo_contract
+    }
+
+    #[test]
+    fn record_deviation() {
+        let mut fpo_contract = setup();
+        testing_env!(get_context(carol(), alice()).build());
+        fpo_contract.push_data("ETH/USD".to_string(), U128(2200), None);
+
+        let score = fpo_contract
+            .get_provider_score(carol(), "ETH/USD".to_string())
+            .unwrap();
+        assert_eq!(1, score.stats.updates);
+        assert_eq!(1000, score.stats.avg_deviation_bps);
+        assert_eq!(9000, score.score);
+
+        // closer to the others again
+        fpo_contract.push_data("ETH/USD".to_string(), U128(2000), None);
+        let score = fpo_contract
+            .get_provider_score(carol(), "ETH/USD".to_string())
+            .unwrap();
+        assert_eq!(2, score.stats.updates);
+        assert_eq!(950, score.stats.avg_deviation_bps);
+    }
+
+    #[test]
+    fn record_stale_incident() {
+        let mut context = get_context(alice(), alice());
+        testing_env!(context.block_timestamp(100).build());
+        let mut fpo_contract = FPOContract::new();
+        fpo_contract.create_pair(
+            "ETH/USD".to_string(),
+            8,
+            U128(2000),
+            Some(PairConfig {
+                heartbeat: Some(10),
+                deviation_threshold: None,
+            }),
+            None,
+        );
+
+        testing_env!(context.block_timestamp(105).build());
+        fpo_contract.push_data("ETH/USD".to_string(), U128(2000), None);
+        testing_env!(context.block_timestamp(200).build());
+        fpo_contract.push_data("ETH/USD".to_string(), U128(2000), None);
+
+        let score = fpo_contract
+            .get_provider_score(alice(), "ETH/USD".to_string())
+            .unwrap();
+        // no other providers to compare against
+        assert_eq!(0, score.stats.updates);
+        assert_eq!(2, score.stats.total_updates);
+        assert_eq!(1, score.stats.stale_incidents);
+        assert_eq!(5000, score.score);
+    }
+
+    #[test]
+    fn consensus_across_decimals_and_heartbeat() {
+        testing_env!(get_context(alice(), alice()).block_timestamp(100).build());
+        let mut fpo_contract = FPOContract::new();
+        fpo_contract.create_pair(
+            "ETH/USD".to_string(),
+            8,
+            U128(4000),
+            Some(PairConfig {
+                heartbeat: Some(10),
+                deviation_threshold: None,
+            }),
+            None,
+        );
+        testing_env!(get_context(bob(), alice()).block_timestamp(100).build());
+        fpo_contract.create_pair("ETH/USD".to_string(), 10, U128(200_000), None, None);
+        testing_env!(get_context(carol(), alice()).block_timestamp(100).build());
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2000), None, None);
+
+        // alice missed her heartbeat and bob's price is rescaled to 2000
+        testing_env!(get_context(carol(), alice()).block_timestamp(200).build());
+        fpo_contract.push_data("ETH/USD".to_string(), U128(2200), None);
+        let score = fpo_contract
+            .get_provider_score(carol(), "ETH/USD".to_string())
+            .unwrap();
+        assert_eq!(1000, score.stats.avg_deviation_bps);
+    }
+
+    #[test]
+    fn consensus_of_first_providers() {
+        testing_env!(get_context(alice(), alice()).build());
+        let mut fpo_contract = FPOContract::new();
+        for i in 0..2 * MAX_CONSENSUS_PROVIDERS + 1 {
+            let price = if i < MAX_CONSENSUS_PROVIDERS {
+                2000
+            } else {
+                8000
+            };
+            let account_id: AccountId = format!("provider{}.near", i).parse().unwrap();
+            testing_env!(get_context(account_id, alice()).build());
+            fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(price), None, None);
+        }
+
+        // only the first providers of the pair are compared against, most of all are at 8000
+        testing_env!(get_context(carol(), alice()).build());
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2000), None, None);
+        fpo_contract.push_data("ETH/USD".to_string(), U128(2000), None);
+        let score = fpo_contract
+            .get_provider_score(carol(), "ETH/USD".to_string())
+            .unwrap();
+        assert_eq!(0, score.stats.avg_deviation_bps);
+    }
+
+    #[test]
+    fn aggregate_min_score() {
+        let mut fpo_contract = setup();
+        testing_env!(get_context(carol(), alice()).build());
+        fpo_contract.push_data("ETH/USD".to_string(), U128(4000), None);
+
+        let pairs = vec!["ETH/USD".to_string(); 3];
+        let providers = vec![alice(), bob(), carol()];
+        assert_eq!(
+            Some(U128(2666)),
+            fpo_contract.aggregate_avg(pairs.clone(), providers.clone(), 0, None)
+        );
+        assert_eq!(
+            Some(U128(2000)),
+            fpo_contract.aggregate_avg(
+                pairs,
+                providers,
+                0,
+                Some(AggregateOptions {
+                    min_score: Some(5000),
+                    ..Default::default()
+                })
+            )
+        );
+    }
+
+    #[test]
+    fn score_without_updates() {
+        let fpo_contract = setup();
+        assert_eq!(
+            Some(ProviderScore {
+                stats: PairScore::default(),
+                score: MAX_SCORE,
+            }),
+            fpo_contract.get_provider_score(alice(), "ETH/USD".to_string())
+        );
+        assert_eq!(
+            None,
+            fpo_contract.get_provider_score(alice(), "BTC/USD".to_string())
+        );
+    }
+}
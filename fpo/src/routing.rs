@@ -0,0 +1,484 @@
+use crate::*;
+use aggregate::AggregateOptions;
+use math::Rounding;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::Timestamp;
+use price_pair::PriceEntry;
+use std::collections::{HashMap, VecDeque};
+
+/// Upper bound for `max_hops` to keep the path search within the gas limit
+const MAX_HOPS: u8 = 4;
+const MAX_ROUTING_PROVIDERS: usize = 16; // maximum number of providers allowed to price a hop
+
+/// A published pair used to convert between two assets
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct ConversionHop {
+    pub pair: String,
+    pub inverted: bool, // The pair was used from quote to base
+    pub price: U128,    // Median price of the pair across the allowed providers
+    pub decimals: u16,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct ConversionRate {
+    pub rate: U128,
+    pub decimals: u16, // Largest amount of decimals of the pairs on the path
+    pub path: Vec<ConversionHop>,
+    pub oldest_update: Timestamp, // Oldest entry that went into the rate
+}
+
+/// Splits a pair name like "ETH/USD" into its assets
+fn split_pair(pair: &str) -> Option<(&str, &str)> {
+    let mut assets = pair.split('/');
+    match (assets.next(), assets.next(), assets.next()) {
+        (Some(base), Some(quote), None) if !base.is_empty() && !quote.is_empty() => {
+            Some((base, quote))
+        }
+        _ => None,
+    }
+}
+
+/// Private contract methods
+impl FPOContract {
+    /// Records that a provider publishes a pair so it can be used for routing
+    pub fn index_pair_provider(&mut self, pair: &str, provider: &AccountId) {
+        let pair = pair.to_string();
+        let mut providers = self.pair_providers.get(&pair).unwrap_or_default();
+        if providers.contains(provider) {
+            return;
+        }
+        providers.push(provider.clone());
+        self.pair_providers.insert(&pair, &providers);
+
+        if let Some((base, quote)) = split_pair(&pair) {
+            for asset in [base, quote].iter() {
+                let asset = asset.to_string();
+                let mut pairs = self.asset_pairs.get(&asset).unwrap_or_default();
+                if !pairs.contains(&pair) {
+                    pairs.push(pair.clone());
+                    self.asset_pairs.insert(&asset, &pairs);
+                }
+            }
+        }
+    }
+
+    /// Removes a provider from the routing index of a pair
+    pub fn unindex_pair_provider(&mut self, pair: &str, provider: &AccountId) {
+        let pair = pair.to_string();
+        let mut providers = self.pair_providers.get(&pair).unwrap_or_default();
+        providers.retain(|account_id| account_id != provider);
+        if !providers.is_empty() {
+            self.pair_providers.insert(&pair, &providers);
+            return;
+        }
+        self.pair_providers.remove(&pair);
+
+        if let Some((base, quote)) = split_pair(&pair) {
+            for asset in [base, quote].iter() {
+                let asset = asset.to_string();
+                let mut pairs = self.asset_pairs.get(&asset).unwrap_or_default();
+                pairs.retain(|p| *p != pair);
+                if pairs.is_empty() {
+                    self.asset_pairs.remove(&asset);
+                } else {
+                    self.asset_pairs.insert(&asset, &pairs);
+                }
+            }
+        }
+    }
+
+    /// Returns the median price of a pair across the allowed providers whose entries pass the
+    /// aggregation filters and were updated at or after `min_last_update`, in the largest amount
+    /// of decimals of the entries, and the oldest update
+    fn get_routing_price(
+        &self,
+        pair: &str,
+        providers: &[AccountId],
+        min_last_update: Timestamp,
+    ) -> Option<(u128, u16, Timestamp)> {
+        let options = AggregateOptions {
+            active_only: true,
+            use_heartbeat: true,
+            exclude_disputed: true,
+            ..Default::default()
+        };
+        let entries: Vec<(PriceEntry, u128)> = self
+            .collect_entries(
+                &vec![pair.to_string(); providers.len()],
+                providers,
+                min_last_update,
+                &options,
+            )
+            .into_iter()
+            .flatten()
+            // the heartbeat takes the place of `min_last_update` for pairs that have one
+            .filter(|(entry, _)| entry.last_update >= min_last_update)
+            .collect();
+
+        let decimals = entries.iter().map(|(entry, _)| entry.decimals).max()?;
+        let oldest_update = entries.iter().map(|(entry, _)| entry.last_update).min()?;
+        let mut prices = aggregate::rescale_prices(&entries, decimals);
+        let price = aggregate::median_price(&mut prices, false)?;
+        Some((price.0, decimals, oldest_update))
+    }
+}
+
+/// Public contract methods
+#[near_bindgen]
+impl FPOContract {
+    /// Returns the providers that publish a pair
+    pub fn get_pair_providers(&self, pair: String) -> Vec<AccountId> {
+        self.pair_providers.get(&pair).unwrap_or_default()
+    }
+
+    /// Finds the shortest path of at most `max_hops` published pairs (or their inverses) from
+    /// `base` to `quote` and returns the rate along it, None if there is no path. Each hop is
+    /// priced by `providers` only, using entries updated within `max_age` nanoseconds if given.
+    pub fn get_conversion_rate(
+        &self,
+        base: String,
+        quote: String,
+        max_hops: u8,
+        providers: Vec<AccountId>,
+        max_age: Option<u64>,
+    ) -> Option<ConversionRate> {
+        assert!(max_hops <= MAX_HOPS, "max_hops can be at most {}", MAX_HOPS);
+        assert!(
+            !providers.is_empty() && providers.len() <= MAX_ROUTING_PROVIDERS,
+            "routing needs between 1 and {} providers",
+            MAX_ROUTING_PROVIDERS
+        );
+        for (i, provider) in providers.iter().enumerate() {
+            assert!(!providers[..i].contains(provider), "duplicate provider");
+        }
+        let min_last_update = max_age
+            .map(|max_age| env::block_timestamp().saturating_sub(max_age))
+            .unwrap_or(0);
+
+        // breadth first search over assets, remembering the hop that reached each asset
+        let mut reached: HashMap<String, Option<(String, ConversionHop, Timestamp)>> =
+            HashMap::new();
+        let mut queue = VecDeque::new();
+        reached.insert(base.clone(), None);
+        queue.push_back((base.clone(), 0u8));
+
+        while let Some((asset, hops)) = queue.pop_front() {
+            if asset == quote || hops == max_hops {
+                continue;
+            }
+            for pair in self.asset_pairs.get(&asset).unwrap_or_default() {
+                let (pair_base, pair_quote) = match split_pair(&pair) {
+                    Some(assets) => assets,
+                    None => continue,
+                };
+                let (next, inverted) = if pair_base == asset {
+                    (pair_quote.to_string(), false)
+                } else {
+                    (pair_base.to_string(), true)
+                };
+                if reached.contains_key(&next) {
+                    continue;
+                }
+                if let Some((price, decimals, last_update)) =
+                    self.get_routing_price(&pair, &providers, min_last_update)
+                {
+                    let hop = ConversionHop {
+                        pair: pair.clone(),
+                        inverted,
+                        price: U128(price),
+                        decimals,
+                    };
+                    reached.insert(next.clone(), Some((asset.clone(), hop, last_update)));
+                    queue.push_back((next, hops + 1));
+                }
+            }
+        }
+
+        // walk back from the quote to collect the path
+        let mut path = vec![];
+        let mut oldest_update = env::block_timestamp();
+        let mut asset = quote;
+        while let Some((previous, hop, last_update)) = reached.get(&asset)?.clone() {
+            path.push(hop);
+            oldest_update = oldest_update.min(last_update);
+            asset = previous;
+        }
+        path.reverse();
+
+        let decimals = path.iter().map(|hop| hop.decimals).max().unwrap_or(0);
+        let mut rate = math::pow10(decimals)?;
+        for hop in path.iter() {
+            let unit = math::pow10(hop.decimals)?;
+            rate = if hop.inverted {
+                math::mul_div(rate, unit, hop.price.0, Rounding::Down)?
+            } else {
+                math::mul_div(rate, hop.price.0, unit, Rounding::Down)?
+            };
+        }
+
+        Some(ConversionRate {
+            rate: U128(rate),
+            decimals,
+            path,
+            oldest_update,
+        })
+    }
+}
+
+/// Conversion routing tests
+#[cfg(test)]
+mod tests {
+
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+    use price_pair::{PairConfig, STORAGE_COST};
+
+    use super::*;
+
+    fn alice() -> AccountId {
+        "alice.near".parse().unwrap()
+    }
+    fn bob() -> AccountId {
+        "bob.near".parse().unwrap()
+    }
+
+    fn get_context(
+        predecessor_account_id: AccountId,
+        current_account_id: AccountId,
+    ) -> VMContextBuilder {
+        let mut builder = VMContextBuilder::new();
+        builder
+            .current_account_id(current_account_id.clone())
+            .signer_account_id("robert.testnet".parse().unwrap())
+            .predecessor_account_id(predecessor_account_id.clone())
+            .attached_deposit(STORAGE_COST);
+        builder
+    }
+
+    /// Creates a contract with NEAR/USD, ETH/USD and EUR/USD published at different times
+    fn setup() -> FPOContract {
+        testing_env!(get_context(alice(), alice()).block_timestamp(100).build());
+        let mut fpo_contract = FPOContract::new();
+        fpo_contract.create_pair("NEAR/USD".to_string(), 4, U128(50_000), None, None);
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(300_000_000_000), None, None);
+
+        testing_env!(get_context(bob(), alice()).block_timestamp(200).build());
+        fpo_contract.create_pair("EUR/USD".to_string(), 4, U128(12_000), None, None);
+        fpo_contract
+    }
+
+    #[test]
+    fn direct_and_inverse_rate() {
+        let fpo_contract = setup();
+        let rate = fpo_contract
+            .get_conversion_rate(
+                "NEAR".to_string(),
+                "USD".to_string(),
+                1,
+                vec![alice(), bob()],
+                None,
+            )
+            .unwrap();
+        assert_eq!(U128(50_000), rate.rate);
+        assert_eq!(4, rate.decimals);
+        assert_eq!(100, rate.oldest_update);
+
+        let rate = fpo_contract
+            .get_conversion_rate(
+                "USD".to_string(),
+                "NEAR".to_string(),
+                1,
+                vec![alice(), bob()],
+                None,
+            )
+            .unwrap();
+        assert_eq!(U128(2_000), rate.rate);
+        assert!(rate.path[0].inverted);
+    }
+
+    #[test]
+    fn multi_hop_rate() {
+        let fpo_contract = setup();
+        let rate = fpo_contract
+            .get_conversion_rate(
+                "NEAR".to_string(),
+                "EUR".to_string(),
+                2,
+                vec![alice(), bob()],
+                None,
+            )
+            .unwrap();
+        // 5 / 1.2 = 4.1666...
+        assert_eq!(U128(41_666), rate.rate);
+        assert_eq!(4, rate.decimals);
+        assert_eq!(100, rate.oldest_update);
+        assert_eq!(
+            vec!["NEAR/USD".to_string(), "EUR/USD".to_string()],
+            rate.path
+                .iter()
+                .map(|hop| hop.pair.clone())
+                .collect::<Vec<String>>()
+        );
+        assert_eq!(
+            vec![false, true],
+            rate.path
+                .iter()
+                .map(|hop| hop.inverted)
+                .collect::<Vec<bool>>()
+        );
+
+        let rate = fpo_contract
+            .get_conversion_rate(
+                "ETH".to_string(),
+                "NEAR".to_string(),
+                2,
+                vec![alice(), bob()],
+                None,
+            )
+            .unwrap();
+        // 3000 / 5 = 600 with the decimals of ETH/USD
+        assert_eq!(U128(60_000_000_000), rate.rate);
+        assert_eq!(8, rate.decimals);
+    }
+
+    #[test]
+    fn rate_across_decimals_and_stale_entries() {
+        let mut fpo_contract = setup();
+        fpo_contract.create_pair("NEAR/USD".to_string(), 6, U128(5_200_000), None, None);
+
+        // median of 5.0 and 5.2 in the larger amount of decimals
+        let rate = fpo_contract
+            .get_conversion_rate(
+                "NEAR".to_string(),
+                "USD".to_string(),
+                1,
+                vec![alice(), bob()],
+                None,
+            )
+            .unwrap();
+        assert_eq!(U128(5_100_000), rate.rate);
+        assert_eq!(6, rate.decimals);
+        assert_eq!(100, rate.oldest_update);
+
+        // alice's entry misses its heartbeat and is left out
+        testing_env!(get_context(alice(), alice()).block_timestamp(300).build());
+        fpo_contract.set_pair_config(
+            "NEAR/USD".to_string(),
+            PairConfig {
+                heartbeat: Some(50),
+                deviation_threshold: None,
+            },
+        );
+        let rate = fpo_contract
+            .get_conversion_rate(
+                "NEAR".to_string(),
+                "USD".to_string(),
+                1,
+                vec![alice(), bob()],
+                None,
+            )
+            .unwrap();
+        assert_eq!(U128(5_200_000), rate.rate);
+        assert_eq!(200, rate.oldest_update);
+    }
+
+    #[test]
+    fn no_path_within_max_hops() {
+        let fpo_contract = setup();
+        assert_eq!(
+            None,
+            fpo_contract.get_conversion_rate(
+                "NEAR".to_string(),
+                "EUR".to_string(),
+                1,
+                vec![alice(), bob()],
+                None
+            )
+        );
+        assert_eq!(
+            None,
+            fpo_contract.get_conversion_rate(
+                "NEAR".to_string(),
+                "BTC".to_string(),
+                3,
+                vec![alice(), bob()],
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn removed_pair_is_not_routed() {
+        let mut fpo_contract = setup();
+        fpo_contract.remove_pair("EUR/USD".to_string());
+        assert!(fpo_contract
+            .get_pair_providers("EUR/USD".to_string())
+            .is_empty());
+        assert_eq!(
+            None,
+            fpo_contract.get_conversion_rate(
+                "NEAR".to_string(),
+                "EUR".to_string(),
+                2,
+                vec![alice(), bob()],
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn rate_from_allowed_providers_within_max_age() {
+        let mut fpo_contract = setup();
+        testing_env!(get_context(bob(), alice()).block_timestamp(200).build());
+        fpo_contract.create_pair("NEAR/USD".to_string(), 4, U128(90_000), None, None);
+
+        // bob's price is left out when only alice is allowed
+        let rate = fpo_contract
+            .get_conversion_rate(
+                "NEAR".to_string(),
+                "USD".to_string(),
+                1,
+                vec![alice()],
+                None,
+            )
+            .unwrap();
+        assert_eq!(U128(50_000), rate.rate);
+
+        // alice's price is too old
+        testing_env!(get_context(bob(), alice()).block_timestamp(250).build());
+        let rate = fpo_contract
+            .get_conversion_rate(
+                "NEAR".to_string(),
+                "USD".to_string(),
+                1,
+                vec![alice(), bob()],
+                Some(100),
+            )
+            .unwrap();
+        assert_eq!(U128(90_000), rate.rate);
+        assert_eq!(200, rate.oldest_update);
+        assert_eq!(
+            None,
+            fpo_contract.get_conversion_rate(
+                "NEAR".to_string(),
+                "USD".to_string(),
+                1,
+                vec![alice()],
+                Some(100)
+            )
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "duplicate provider")]
+    fn rate_with_duplicate_provider() {
+        let fpo_contract = setup();
+        fpo_contract.get_conversion_rate(
+            "NEAR".to_string(),
+            "USD".to_string(),
+            1,
+            vec![alice(), alice()],
+            None,
+        );
+    }
+}
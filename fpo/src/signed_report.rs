@@ -0,0 +1,243 @@
+use crate::*;
+use ed25519_dalek::{PublicKey as Ed25519PublicKey, Signature};
+use near_sdk::json_types::Base64VecU8;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{CurveType, PublicKey, Timestamp};
+use std::convert::TryFrom;
+
+/// Maximum age of a report when it is submitted, 5 minutes in nanoseconds
+pub const MAX_REPORT_AGE: u64 = 5 * 60 * 1_000_000_000;
+
+/// Price update signed off-chain by a provider, the signature covers its borsh serialization
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Debug, Clone)]
+pub struct PriceReport {
+    pub oracle: AccountId, // Contract the report is meant for
+    pub provider: AccountId,
+    pub pair: String,
+    pub price: U128,
+    pub nonce: u64,           // Has to increase with every report
+    pub timestamp: Timestamp, // Time of signing
+}
+
+/// Checks an ed25519 signature of a message
+pub fn verify_signature(public_key: &PublicKey, message: &[u8], signature: &[u8]) -> bool {
+    let public_key = match Ed25519PublicKey::from_bytes(&public_key.as_bytes()[1..]) {
+        Ok(public_key) => public_key,
+        Err(_) => return false,
+    };
+    match Signature::try_from(signature) {
+        Ok(signature) => public_key.verify_strict(message, &signature).is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// Public contract methods
+#[near_bindgen]
+impl FPOContract {
+    /// Sets or clears the ed25519 key the calling provider signs reports with
+    pub fn set_signing_key(&mut self, public_key: Option<PublicKey>) {
+        if let Some(public_key) = &public_key {
+            assert_eq!(
+                public_key.curve_type(),
+                CurveType::ED25519,
+                "signing key must be an ed25519 key"
+            );
+        }
+        let mut provider = self.get_provider_expect(&env::predecessor_account_id());
+        provider.signing_key = public_key;
+        self.set_provider(&env::predecessor_account_id(), provider);
+    }
+
+    /// Returns the key a provider signs reports with
+    pub fn get_signing_key(&self, provider: AccountId) -> Option<PublicKey> {
+        self.get_provider_option(&provider)
+            .and_then(|provider| provider.signing_key)
+    }
+
+    /// Returns the nonce of the last report submitted for a provider
+    pub fn get_report_nonce(&self, provider: AccountId) -> u64 {
+        self.get_provider_expect(&provider).report_nonce
+    }
+
+    /// Applies a price report signed by a provider, which can be submitted by any account
+    pub fn submit_signed_report(&mut self, report: PriceReport, signature: Base64VecU8) {
+        self.assert_not_paused();
+        assert_eq!(
+            report.oracle,
+            env::current_account_id(),
+            "report is for another oracle"
+        );
+        let now = env::block_timestamp();
+        assert!(report.timestamp <= now, "report timestamp is in the future");
+        assert!(
+            now - report.timestamp <= MAX_REPORT_AGE,
+            "report has expired"
+        );
+
+        let mut provider = self.get_provider_expect(&report.provider);
+        provider.assert_active();
+        let signing_key = provider
+            .signing_key
+            .as_ref()
+            .expect("provider has no signing key");
+        let message = report.try_to_vec().expect("failed to serialize report");
+        assert!(
+            verify_signature(signing_key, &message, &signature.0),
+            "invalid signature"
+        );
+        assert!(
+            report.nonce > provider.report_nonce,
+            "nonce has already been used"
+        );
+        provider.report_nonce = report.nonce;
+
+        let pair_name = format!("{}:{}", report.pair, report.provider);
+        provider.assert_no_commit_reveal(&pair_name);
+        self.assert_no_committee(&pair_name);
+        self.update_price(
+            provider,
+            &report.pair,
+            report.price,
+            &report.provider,
+            &report.provider,
+        );
+    }
+}
+
+/// Signed report tests
+#[cfg(test)]
+mod tests {
+
+    use ed25519_dalek::{ExpandedSecretKey, SecretKey};
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+    use price_pair::STORAGE_COST;
+
+    use super::*;
+
+    fn alice() -> AccountId {
+        "alice.near".parse().unwrap()
+    }
+    fn bob() -> AccountId {
+        "bob.near".parse().unwrap()
+    }
+    fn fpo() -> AccountId {
+        "fpo.near".parse().unwrap()
+    }
+
+    fn get_context(
+        predecessor_account_id: AccountId,
+        current_account_id: AccountId,
+    ) -> VMContextBuilder {
+        let mut builder = VMContextBuilder::new();
+        builder
+            .current_account_id(current_account_id.clone())
+            .signer_account_id("robert.testnet".parse().unwrap())
+            .predecessor_account_id(predecessor_account_id.clone())
+            .attached_deposit(STORAGE_COST);
+        builder
+    }
+
+    fn secret_key() -> SecretKey {
+        SecretKey::from_bytes(&[7; 32]).unwrap()
+    }
+
+    fn public_key() -> PublicKey {
+        let mut bytes = vec![0];
+        bytes.extend_from_slice(Ed25519PublicKey::from(&secret_key()).as_bytes());
+        PublicKey::try_from(bytes).unwrap()
+    }
+
+    fn report(nonce: u64, price: u128) -> PriceReport {
+        PriceReport {
+            oracle: fpo(),
+            provider: alice(),
+            pair: "ETH/USD".to_string(),
+            price: U128(price),
+            nonce,
+            timestamp: 100,
+        }
+    }
+
+    fn sign(report: &PriceReport) -> Base64VecU8 {
+        let secret_key = secret_key();
+        let signature = ExpandedSecretKey::from(&secret_key).sign(
+            &report.try_to_vec().unwrap(),
+            &Ed25519PublicKey::from(&secret_key),
+        );
+        Base64VecU8(signature.to_bytes().to_vec())
+    }
+
+    /// Creates a contract where alice provides ETH/USD and signs reports, submitted by bob
+    fn setup() -> FPOContract {
+        testing_env!(get_context(alice(), fpo()).build());
+        let mut fpo_contract = FPOContract::new();
+        fpo_contract.create_pair("ETH/USD".to_string(), 8, U128(2000), None, None);
+        fpo_contract.set_signing_key(Some(public_key()));
+
+        testing_env!(get_context(bob(), fpo()).block_timestamp(150).build());
+        fpo_contract
+    }
+
+    #[test]
+    fn submit_report() {
+        let mut fpo_contract = setup();
+        let report = report(1, 2500);
+        fpo_contract.submit_signed_report(report.clone(), sign(&report));
+
+        let entry = fpo_contract
+            .get_entry("ETH/USD".to_string(), alice())
+            .unwrap();
+        assert_eq!(U128(2500), entry.price);
+        assert_eq!(150, entry.last_update);
+        assert_eq!(Some(alice()), entry.pusher);
+        assert_eq!(1, fpo_contract.get_report_nonce(alice()));
+    }
+
+    #[test]
+    #[should_panic(expected = "nonce has already been used")]
+    fn replay_report() {
+        let mut fpo_contract = setup();
+        let report = report(1, 2500);
+        fpo_contract.submit_signed_report(report.clone(), sign(&report));
+        fpo_contract.submit_signed_report(report.clone(), sign(&report));
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid signature")]
+    fn tampered_report() {
+        let mut fpo_contract = setup();
+        let signature = sign(&report(1, 2500));
+        fpo_contract.submit_signed_report(report(1, 9000), signature);
+    }
+
+    #[test]
+    #[should_panic(expected = "report has expired")]
+    fn expired_report() {
+        let mut fpo_contract = setup();
+        testing_env!(get_context(bob(), fpo())
+            .block_timestamp(101 + MAX_REPORT_AGE)
+            .build());
+        let report = report(1, 2500);
+        fpo_contract.submit_signed_report(report.clone(), sign(&report));
+    }
+
+    #[test]
+    #[should_panic(expected = "report is for another oracle")]
+    fn report_for_other_oracle() {
+        let mut fpo_contract = setup();
+        let mut report = report(1, 2500);
+        report.oracle = bob();
+        fpo_contract.submit_signed_report(report.clone(), sign(&report));
+    }
+
+    #[test]
+    #[should_panic(expected = "provider has no signing key")]
+    fn report_without_signing_key() {
+        let mut fpo_contract = setup();
+        testing_env!(get_context(alice(), fpo()).block_timestamp(150).build());
+        fpo_contract.set_signing_key(None);
+        let report = report(1, 2500);
+        fpo_contract.submit_signed_report(report.clone(), sign(&report));
+    }
+}
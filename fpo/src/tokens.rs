@@ -0,0 +1,357 @@
+use crate::*;
+use admin::Role;
+use aggregate::AggregateOptions;
+use near_sdk::serde::{Deserialize, Serialize};
+use price_pair::PriceEntry;
+
+const DEFAULT_QUOTE: &str = "USD"; // quote asset of get_token_prices()
+const MAX_TOKEN_PROVIDERS: usize = 16; // maximum number of providers allowed to price a token
+
+/// The asset a NEP-141 token contract stands for in pair names
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct TokenAsset {
+    pub asset: String, // Ticker used in pair names, e.g. "NEAR" for wrap.near
+    pub decimals: u8,  // Decimals of the token contract
+    pub providers: Vec<AccountId>, // Providers whose prices are used for the token
+}
+
+/// The price of a token, aggregated over the active providers of its pair
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct TokenPrice {
+    pub token_id: AccountId,
+    pub pair: String,
+    pub price: U128,
+    pub decimals: u16,      // Amount of decimals of the price
+    pub token_decimals: u8, // Amount of decimals of the token
+}
+
+/// Public contract methods
+#[near_bindgen]
+impl FPOContract {
+    /// Registers the asset of a token contract, replacing any existing registration
+    #[payable]
+    pub fn set_token_asset(&mut self, token_id: AccountId, token_asset: TokenAsset) {
+        self.assert_role(Role::Admin);
+        assert!(
+            !token_asset.asset.is_empty() && !token_asset.asset.contains('/'),
+            "invalid asset"
+        );
+        assert!(
+            !token_asset.providers.is_empty() && token_asset.providers.len() <= MAX_TOKEN_PROVIDERS,
+            "a token needs between 1 and {} providers",
+            MAX_TOKEN_PROVIDERS
+        );
+        for (i, provider) in token_asset.providers.iter().enumerate() {
+            assert!(
+                !token_asset.providers[..i].contains(provider),
+                "duplicate provider"
+            );
+        }
+        let initial_storage_usage = env::storage_usage();
+
+        self.token_assets.insert(&token_id, &token_asset);
+
+        // check for storage deposit
+        let storage_cost = env::storage_byte_cost()
+            * u128::from(env::storage_usage().saturating_sub(initial_storage_usage));
+        assert!(
+            storage_cost <= env::attached_deposit(),
+            "Insufficient storage, need {}",
+            storage_cost
+        );
+    }
+
+    /// Removes the registration of a token contract
+    pub fn remove_token_asset(&mut self, token_id: AccountId) {
+        self.assert_role(Role::Admin);
+        assert!(
+            self.token_assets.remove(&token_id).is_some(),
+            "token is not registered"
+        );
+    }
+
+    /// Returns the asset a token contract is registered as
+    pub fn get_token_asset(&self, token_id: AccountId) -> Option<TokenAsset> {
+        self.token_assets.get(&token_id)
+    }
+
+    /// Returns the median price of "{asset}/{quote}" across the allowed providers of the token
+    /// that are active, didn't miss their heartbeat and updated within `max_age` nanoseconds if
+    /// given. Prices are rescaled to the most decimals among them. None if the token is not
+    /// registered or there is no such price
+    pub fn get_token_price(
+        &self,
+        token_id: AccountId,
+        quote: String,
+        max_age: Option<u64>,
+    ) -> Option<TokenPrice> {
+        let token_asset = self.get_token_asset(token_id.clone())?;
+        let pair = format!("{}/{}", token_asset.asset, quote);
+        let min_last_update = max_age
+            .map(|max_age| env::block_timestamp().saturating_sub(max_age))
+            .unwrap_or(0);
+        let entries: Vec<(PriceEntry, u128)> = self
+            .collect_entries(
+                &vec![pair.clone(); token_asset.providers.len()],
+                &token_asset.providers,
+                min_last_update,
+                &AggregateOptions {
+                    active_only: true,
+                    use_heartbeat: true,
+                    ..Default::default()
+                },
+            )
+            .into_iter()
+            .flatten()
+            // the heartbeat takes the place of `min_last_update` for pairs that have one
+            .filter(|(entry, _)| entry.last_update >= min_last_update)
+            .collect();
+
+        let decimals = entries.iter().map(|(entry, _)| entry.decimals).max()?;
+        let mut prices = aggregate::rescale_prices(&entries, decimals);
+        let price = aggregate::median_price(&mut prices, false)?;
+        Some(TokenPrice {
+            token_id,
+            pair,
+            price,
+            decimals,
+            token_decimals: token_asset.decimals,
+        })
+    }
+
+    /// Returns the USD prices of multiple tokens, see `get_token_price()`
+    pub fn get_token_prices(
+        &self,
+        token_ids: Vec<AccountId>,
+        max_age: Option<u64>,
+    ) -> Vec<Option<TokenPrice>> {
+        token_ids
+            .into_iter()
+            .map(|token_id| self.get_token_price(token_id, DEFAULT_QUOTE.to_string(), max_age))
+            .collect()
+    }
+}
+
+/// Token registry tests
+#[cfg(test)]
+mod tests {
+
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+    use price_pair::{PairConfig, STORAGE_COST};
+
+    use super::*;
+
+    fn alice() -> AccountId {
+        "alice.near".parse().unwrap()
+    }
+    fn bob() -> AccountId {
+        "bob.near".parse().unwrap()
+    }
+    fn carol() -> AccountId {
+        "carol.near".parse().unwrap()
+    }
+    fn wnear() -> AccountId {
+        "wrap.near".parse().unwrap()
+    }
+    fn dave() -> AccountId {
+        "dave.near".parse().unwrap()
+    }
+    fn usdc() -> AccountId {
+        "usdc.near".parse().unwrap()
+    }
+
+    fn get_context(
+        predecessor_account_id: AccountId,
+        current_account_id: AccountId,
+    ) -> VMContextBuilder {
+        let mut builder = VMContextBuilder::new();
+        builder
+            .current_account_id(current_account_id.clone())
+            .signer_account_id("robert.testnet".parse().unwrap())
+            .predecessor_account_id(predecessor_account_id.clone())
+            .attached_deposit(STORAGE_COST);
+        builder
+    }
+
+    fn near_asset() -> TokenAsset {
+        TokenAsset {
+            asset: "NEAR".to_string(),
+            decimals: 24,
+            providers: vec![bob(), carol()],
+        }
+    }
+
+    /// Creates a contract owned by alice with wrap.near registered and NEAR/USD by bob and carol
+    fn setup() -> FPOContract {
+        testing_env!(get_context(alice(), alice()).build());
+        let mut fpo_contract = FPOContract::new();
+        fpo_contract.set_token_asset(wnear(), near_asset());
+
+        testing_env!(get_context(bob(), alice()).build());
+        fpo_contract.create_pair("NEAR/USD".to_string(), 4, U128(50_000), None, None);
+        testing_env!(get_context(carol(), alice()).build());
+        fpo_contract.create_pair("NEAR/USD".to_string(), 4, U128(60_000), None, None);
+        fpo_contract
+    }
+
+    #[test]
+    fn get_token_price() {
+        let fpo_contract = setup();
+        assert_eq!(
+            Some(TokenPrice {
+                token_id: wnear(),
+                pair: "NEAR/USD".to_string(),
+                price: U128(55_000),
+                decimals: 4,
+                token_decimals: 24,
+            }),
+            fpo_contract.get_token_price(wnear(), "USD".to_string(), None)
+        );
+        assert_eq!(
+            None,
+            fpo_contract.get_token_price(wnear(), "EUR".to_string(), None)
+        );
+    }
+
+    #[test]
+    fn get_token_prices() {
+        let fpo_contract = setup();
+        let prices = fpo_contract.get_token_prices(vec![wnear(), usdc()], None);
+        assert_eq!(U128(55_000), prices[0].as_ref().unwrap().price);
+        assert_eq!(None, prices[1]);
+    }
+
+    #[test]
+    fn token_price_skips_stale_entries() {
+        let mut fpo_contract = setup();
+        fpo_contract.set_pair_config(
+            "NEAR/USD".to_string(),
+            PairConfig {
+                heartbeat: Some(10),
+                deviation_threshold: None,
+            },
+        );
+        testing_env!(get_context(alice(), alice())
+            .block_timestamp(env::block_timestamp() + 100)
+            .build());
+        assert_eq!(
+            U128(50_000),
+            fpo_contract
+                .get_token_price(wnear(), "USD".to_string(), None)
+                .unwrap()
+                .price
+        );
+    }
+
+    #[test]
+    fn token_price_ignores_other_providers() {
+        let mut fpo_contract = setup();
+        testing_env!(get_context(dave(), alice()).build());
+        fpo_contract.create_pair("NEAR/USD".to_string(), 4, U128(1_000_000), None, None);
+        assert_eq!(
+            U128(55_000),
+            fpo_contract
+                .get_token_price(wnear(), "USD".to_string(), None)
+                .unwrap()
+                .price
+        );
+    }
+
+    #[test]
+    fn token_price_with_max_age() {
+        let mut fpo_contract = setup();
+        testing_env!(get_context(carol(), alice())
+            .block_timestamp(env::block_timestamp() + 100)
+            .build());
+        fpo_contract.push_data("NEAR/USD".to_string(), U128(70_000), None);
+
+        let price = |max_age| {
+            fpo_contract
+                .get_token_price(wnear(), "USD".to_string(), max_age)
+                .map(|token_price| token_price.price)
+        };
+        assert_eq!(Some(U128(60_000)), price(None));
+        assert_eq!(Some(U128(70_000)), price(Some(50)));
+        assert_eq!(Some(U128(70_000)), price(Some(0)));
+    }
+
+    #[test]
+    fn token_price_across_decimals() {
+        testing_env!(get_context(alice(), alice()).build());
+        let mut fpo_contract = FPOContract::new();
+        fpo_contract.set_token_asset(wnear(), near_asset());
+        testing_env!(get_context(bob(), alice()).build());
+        fpo_contract.create_pair("NEAR/USD".to_string(), 4, U128(50_000), None, None);
+        testing_env!(get_context(carol(), alice()).build());
+        fpo_contract.create_pair("NEAR/USD".to_string(), 6, U128(6_000_000), None, None);
+
+        let token_price = fpo_contract
+            .get_token_price(wnear(), "USD".to_string(), None)
+            .unwrap();
+        assert_eq!(U128(5_500_000), token_price.price);
+        assert_eq!(6, token_price.decimals);
+    }
+
+    #[test]
+    fn remove_token_asset() {
+        let mut fpo_contract = setup();
+        testing_env!(get_context(alice(), alice()).build());
+        fpo_contract.remove_token_asset(wnear());
+        assert_eq!(None, fpo_contract.get_token_asset(wnear()));
+        assert_eq!(
+            None,
+            fpo_contract.get_token_price(wnear(), "USD".to_string(), None)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "missing role Admin")]
+    fn set_token_asset_without_role() {
+        let mut fpo_contract = setup();
+        fpo_contract.set_token_asset(usdc(), near_asset());
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid asset")]
+    fn set_invalid_token_asset() {
+        testing_env!(get_context(alice(), alice()).build());
+        let mut fpo_contract = FPOContract::new();
+        fpo_contract.set_token_asset(
+            wnear(),
+            TokenAsset {
+                asset: "NEAR/USD".to_string(),
+                decimals: 24,
+                providers: vec![bob()],
+            },
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "a token needs between 1 and 16 providers")]
+    fn set_token_asset_without_providers() {
+        testing_env!(get_context(alice(), alice()).build());
+        let mut fpo_contract = FPOContract::new();
+        fpo_contract.set_token_asset(
+            wnear(),
+            TokenAsset {
+                providers: vec![],
+                ..near_asset()
+            },
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "duplicate provider")]
+    fn set_token_asset_with_duplicate_providers() {
+        testing_env!(get_context(alice(), alice()).build());
+        let mut fpo_contract = FPOContract::new();
+        fpo_contract.set_token_asset(
+            wnear(),
+            TokenAsset {
+                providers: vec![bob(), bob()],
+                ..near_asset()
+            },
+        );
+    }
+}
@@ -0,0 +1,356 @@
+use crate::*;
+use near_sdk::json_types::Base64VecU8;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{Balance, Gas, Promise, Timestamp};
+use price_pair::PriceEntry;
+use std::io::{Error, ErrorKind, Result as IoResult, Write};
+
+const STATE_KEY: &[u8] = b"STATE";
+const GAS_FOR_MIGRATE: Gas = Gas(50_000_000_000_000);
+const NO_DEPOSIT: Balance = 0;
+
+/// Length of a `PriceEntryV0` as it was stored before entries were versioned
+const PRICE_ENTRY_V0_LEN: usize = 26;
+/// Length of a `ProviderV0` as it was stored before providers were versioned
+const PROVIDER_V0_LEN: usize = 21;
+
+#[derive(
+    BorshDeserialize, BorshSerialize, Deserialize, Serialize, Debug, Clone, Copy, PartialEq,
+)]
+pub enum StateVersion {
+    V0, // Providers only, stored without a state version
+    V1, // Added the state version and everything built on providers since
+}
+
+pub const CURRENT_STATE_VERSION: StateVersion = StateVersion::V1;
+
+/// Contract state before the state was versioned
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct FPOContractV0 {
+    pub providers: LookupMap<AccountId, VersionedProvider>,
+}
+
+/// Price entry as stored before entries were versioned
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct PriceEntryV0 {
+    pub price: U128,
+    pub decimals: u16,
+    pub last_update: Timestamp,
+}
+
+/// Provider as stored before providers were versioned
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ProviderV0 {
+    pub query_fee: u128,
+    pub pairs: LookupMap<String, VersionedPriceEntry>,
+}
+
+/// Stored representation of a `PriceEntry`, migrated to the latest layout when read.
+/// Only use as a collection value, unversioned entries are recognized by their length.
+#[allow(clippy::large_enum_variant)]
+pub enum VersionedPriceEntry {
+    V0(PriceEntryV0), // untagged
+    V1(PriceEntry),
+}
+
+/// Stored representation of a `Provider`, migrated to the latest layout when read.
+/// Only use as a collection value, unversioned providers are recognized by their length.
+pub enum VersionedProvider {
+    V0(ProviderV0), // untagged
+    V1(Provider),
+}
+
+impl BorshSerialize for VersionedPriceEntry {
+    fn serialize<W: Write>(&self, writer: &mut W) -> IoResult<()> {
+        match self {
+            Self::V0(entry) => BorshSerialize::serialize(entry, writer),
+            Self::V1(entry) => {
+                BorshSerialize::serialize(&1u8, writer)?;
+                BorshSerialize::serialize(entry, writer)
+            }
+        }
+    }
+}
+
+impl BorshDeserialize for VersionedPriceEntry {
+    fn deserialize(buf: &mut &[u8]) -> IoResult<Self> {
+        if buf.len() == PRICE_ENTRY_V0_LEN {
+            return Ok(Self::V0(BorshDeserialize::deserialize(buf)?));
+        }
+        match <u8 as BorshDeserialize>::deserialize(buf)? {
+            1 => Ok(Self::V1(BorshDeserialize::deserialize(buf)?)),
+            version => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("unknown price entry version {}", version),
+            )),
+        }
+    }
+}
+
+impl BorshSerialize for VersionedProvider {
+    fn serialize<W: Write>(&self, writer: &mut W) -> IoResult<()> {
+        match self {
+            Self::V0(provider) => BorshSerialize::serialize(provider, writer),
+            Self::V1(provider) => {
+                BorshSerialize::serialize(&1u8, writer)?;
+                BorshSerialize::serialize(provider, writer)
+            }
+        }
+    }
+}
+
+impl BorshDeserialize for VersionedProvider {
+    fn deserialize(buf: &mut &[u8]) -> IoResult<Self> {
+        if buf.len() == PROVIDER_V0_LEN {
+            return Ok(Self::V0(BorshDeserialize::deserialize(buf)?));
+        }
+        match <u8 as BorshDeserialize>::deserialize(buf)? {
+            1 => Ok(Self::V1(BorshDeserialize::deserialize(buf)?)),
+            version => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("unknown provider version {}", version),
+            )),
+        }
+    }
+}
+
+impl From<VersionedPriceEntry> for PriceEntry {
+    fn from(entry: VersionedPriceEntry) -> Self {
+        match entry {
+            VersionedPriceEntry::V0(entry) => PriceEntry {
+                price: entry.price,
+                decimals: entry.decimals,
+                last_update: entry.last_update,
+                round_id: 0,
+                pusher: None,
+                config: Default::default(),
+                breaker: None,
+                pending: None,
+                commit_reveal: None,
+            },
+            VersionedPriceEntry::V1(entry) => entry,
+        }
+    }
+}
+
+impl From<PriceEntry> for VersionedPriceEntry {
+    fn from(entry: PriceEntry) -> Self {
+        Self::V1(entry)
+    }
+}
+
+impl From<VersionedProvider> for Provider {
+    /// Pairs of unversioned providers are listed when they are pushed to next
+    fn from(provider: VersionedProvider) -> Self {
+        match provider {
+            VersionedProvider::V0(provider) => Provider {
+                query_fee: provider.query_fee,
+                pairs: provider.pairs,
+                ..Provider::new()
+            },
+            VersionedProvider::V1(provider) => provider,
+        }
+    }
+}
+
+impl From<Provider> for VersionedProvider {
+    fn from(provider: Provider) -> Self {
+        Self::V1(provider)
+    }
+}
+
+impl From<FPOContractV0> for FPOContract {
+    /// Makes the contract account the owner of an unversioned contract
+    fn from(old: FPOContractV0) -> Self {
+        Self {
+            providers: old.providers,
+            owner: env::current_account_id(),
+            ..FPOContract::new()
+        }
+    }
+}
+
+/// Public contract methods
+#[near_bindgen]
+impl FPOContract {
+    /// Deploys new contract code and migrates the state in the same batch, so a failing
+    /// migration reverts the deployment. Can only be called by the owner.
+    pub fn upgrade(&self, code: Base64VecU8) -> Promise {
+        self.assert_owner();
+        Promise::new(env::current_account_id())
+            .deploy_contract(code.into())
+            .function_call("migrate".to_string(), vec![], NO_DEPOSIT, GAS_FOR_MIGRATE)
+    }
+
+    /// Migrates the contract state from any previous layout to the current one, picked by
+    /// the state version that follows the providers. Providers and price entries are migrated
+    /// lazily when they are written again.
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let state = env::storage_read(STATE_KEY).expect("no state to migrate");
+        let mut buf = &state[..];
+        <LookupMap<AccountId, VersionedProvider>>::deserialize(&mut buf)
+            .expect("unknown state layout");
+        let state_version = if buf.is_empty() {
+            StateVersion::V0
+        } else {
+            <StateVersion as BorshDeserialize>::deserialize(&mut buf)
+                .expect("unknown state version")
+        };
+
+        match state_version {
+            StateVersion::V0 => FPOContractV0::try_from_slice(&state)
+                .expect("unknown state layout")
+                .into(),
+            StateVersion::V1 => Self::try_from_slice(&state).expect("unknown state layout"),
+        }
+    }
+
+    /// Returns the layout version of the contract state
+    pub fn get_state_version(&self) -> StateVersion {
+        self.state_version
+    }
+}
+
+/// Migration tests
+#[cfg(test)]
+mod tests {
+
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+    use price_pair::STORAGE_COST;
+
+    use super::*;
+
+    fn alice() -> AccountId {
+        "alice.near".parse().unwrap()
+    }
+    fn fpo() -> AccountId {
+        "fpo.near".parse().unwrap()
+    }
+
+    fn get_context(
+        predecessor_account_id: AccountId,
+        current_account_id: AccountId,
+    ) -> VMContextBuilder {
+        let mut builder = VMContextBuilder::new();
+        builder
+            .current_account_id(current_account_id.clone())
+            .signer_account_id("robert.testnet".parse().unwrap())
+            .predecessor_account_id(predecessor_account_id.clone())
+            .attached_deposit(STORAGE_COST);
+        builder
+    }
+
+    /// Prefix of the price entries of unversioned providers
+    fn legacy_pairs_prefix() -> Vec<u8> {
+        vec![0]
+    }
+
+    /// Writes a provider with a single pair the way the unversioned contract stored it
+    fn write_legacy_provider(account_id: &AccountId, pair: &str, price: u128) {
+        let mut pairs: LookupMap<String, PriceEntryV0> = LookupMap::new(legacy_pairs_prefix());
+        pairs.insert(
+            &format!("{}:{}", pair, account_id),
+            &PriceEntryV0 {
+                price: U128(price),
+                decimals: 8,
+                last_update: 0,
+            },
+        );
+
+        let mut providers: LookupMap<AccountId, ProviderV0> =
+            LookupMap::new(FPOStorageKeys::Providers);
+        providers.insert(
+            account_id,
+            &ProviderV0 {
+                query_fee: 0,
+                pairs: LookupMap::new(legacy_pairs_prefix()),
+            },
+        );
+    }
+
+    #[test]
+    fn migrate_from_v0() {
+        testing_env!(get_context(fpo(), fpo()).build());
+        write_legacy_provider(&alice(), "ETH/USD", 2500);
+        env::state_write(&FPOContractV0 {
+            providers: LookupMap::new(FPOStorageKeys::Providers),
+        });
+
+        let fpo_contract = FPOContract::migrate();
+        assert_eq!(fpo(), fpo_contract.get_owner());
+        assert_eq!(CURRENT_STATE_VERSION, fpo_contract.get_state_version());
+        assert!(!fpo_contract.is_paused());
+
+        let entry = fpo_contract
+            .get_entry("ETH/USD".to_string(), alice())
+            .unwrap();
+        assert_eq!(U128(2500), entry.price);
+        assert_eq!(0, entry.round_id);
+    }
+
+    #[test]
+    fn migrate_current_state() {
+        testing_env!(get_context(alice(), fpo()).build());
+        env::state_write(&FPOContract::new());
+
+        let fpo_contract = FPOContract::migrate();
+        assert_eq!(alice(), fpo_contract.get_owner());
+        assert_eq!(CURRENT_STATE_VERSION, fpo_contract.get_state_version());
+    }
+
+    #[test]
+    #[should_panic(expected = "unknown state version")]
+    fn migrate_unknown_state_version() {
+        testing_env!(get_context(alice(), fpo()).build());
+        let mut state = LookupMap::<AccountId, VersionedProvider>::new(FPOStorageKeys::Providers)
+            .try_to_vec()
+            .unwrap();
+        state.push(u8::MAX);
+        env::storage_write(STATE_KEY, &state);
+
+        FPOContract::migrate();
+    }
+
+    #[test]
+    fn push_data_migrates_legacy_entry() {
+        testing_env!(get_context(alice(), fpo()).build());
+        write_legacy_provider(&alice(), "ETH/USD", 2500);
+        let mut fpo_contract = FPOContract::new();
+
+        fpo_contract.push_data("ETH/USD".to_string(), U128(3000), None);
+        let entry = fpo_contract
+            .get_entry("ETH/USD".to_string(), alice())
+            .unwrap();
+        assert_eq!(U128(3000), entry.price);
+        assert_eq!(1, entry.round_id);
+        assert_eq!(
+            vec!["ETH/USD".to_string()],
+            fpo_contract
+                .get_provider_expect(&alice())
+                .get_pair_names(&alice())
+        );
+
+        // the entry and provider were written back in the versioned layout
+        let pairs: LookupMap<String, VersionedPriceEntry> = LookupMap::new(legacy_pairs_prefix());
+        assert!(matches!(
+            pairs.get(&format!("ETH/USD:{}", alice())),
+            Some(VersionedPriceEntry::V1(_))
+        ));
+        assert!(matches!(
+            fpo_contract.providers.get(&alice()),
+            Some(VersionedProvider::V1(_))
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "only the owner can call this method")]
+    fn upgrade_not_owner() {
+        testing_env!(get_context(alice(), fpo()).build());
+        let fpo_contract = FPOContract::new();
+        testing_env!(get_context(fpo(), fpo()).build());
+        fpo_contract.upgrade(Base64VecU8(vec![]));
+    }
+}
@@ -9,7 +9,7 @@ near_sdk_sim::lazy_static_include::lazy_static_include_bytes! {
 }
 
 pub const DEFAULT_GAS: u64 = 300_000_000_000_000;
-pub const STORAGE_COST: u128 = 1_700_000_000_000_000_000_000;
+pub const STORAGE_COST: u128 = 25_060_000_000_000_000_000_000;
 
 fn init() -> (UserAccount, ContractAccount<FPOContractContract>) {
     let root = init_simulator(None);
@@ -34,7 +34,7 @@ fn simulate_create_pair() {
     root.call(
         fpo.account_id(),
         "create_pair",
-        &json!(["ETH/USD".to_string(), 8, U128(2000)])
+        &json!(["ETH/USD".to_string(), 8, U128(2000), null, null])
             .to_string()
             .into_bytes(),
         DEFAULT_GAS,
@@ -70,14 +70,14 @@ fn simulate_create_smae_pair() {
     root.call(
         fpo.account_id(),
         "create_pair",
-        &json!(["ETH/USD".to_string(), 8, U128(2000)])
+        &json!(["ETH/USD".to_string(), 8, U128(2000), null, null])
             .to_string()
             .into_bytes(),
         DEFAULT_GAS,
         STORAGE_COST, // attached deposit
     );
 
-    let err = call!(root, fpo.create_pair("ETH/USD".to_string(), 8, U128(2000))).promise_errors();
+    let err = call!(root, fpo.create_pair("ETH/USD".to_string(), 8, U128(2000), None, None)).promise_errors();
     println!("ERROR: {:?}", err);
 }
 
@@ -91,7 +91,7 @@ fn simulate_push_data() {
     root.call(
         fpo.account_id(),
         "create_pair",
-        &json!(["ETH/USD".to_string(), 8, U128(2000)])
+        &json!(["ETH/USD".to_string(), 8, U128(2000), null, null])
             .to_string()
             .into_bytes(),
         DEFAULT_GAS,
@@ -112,7 +112,7 @@ fn simulate_push_data() {
     );
 
     // update the data
-    call!(root, fpo.push_data("ETH/USD".to_string(), U128(4000))).assert_success();
+    call!(root, fpo.push_data("ETH/USD".to_string(), U128(4000), None)).assert_success();
 
     // get the updated data
     let price_entry = call!(
@@ -140,7 +140,7 @@ fn simulate_different_providers() {
     root.call(
         fpo.account_id(),
         "create_pair",
-        &json!(["ETH/USD".to_string(), 8, U128(2000)])
+        &json!(["ETH/USD".to_string(), 8, U128(2000), null, null])
             .to_string()
             .into_bytes(),
         DEFAULT_GAS,
@@ -157,7 +157,7 @@ fn simulate_different_providers() {
     bob.call(
         fpo.account_id(),
         "create_pair",
-        &json!(["ETH/USD".to_string(), 8, U128(4000)])
+        &json!(["ETH/USD".to_string(), 8, U128(4000), null, null])
             .to_string()
             .into_bytes(),
         DEFAULT_GAS,
@@ -205,7 +205,7 @@ fn simulate_different_pairs() {
     bob.call(
         fpo.account_id(),
         "create_pair",
-        &json!(["ETH / USD".to_string(), 8, U128(4000)])
+        &json!(["ETH / USD".to_string(), 8, U128(4000), null, null])
             .to_string()
             .into_bytes(),
         DEFAULT_GAS,
@@ -221,7 +221,7 @@ fn simulate_different_pairs() {
     bob.call(
         fpo.account_id(),
         "create_pair",
-        &json!(["BTC / USD".to_string(), 8, U128(45000)])
+        &json!(["BTC / USD".to_string(), 8, U128(45000), null, null])
             .to_string()
             .into_bytes(),
         DEFAULT_GAS,
@@ -271,7 +271,7 @@ fn simulate_agg_avg() {
     root.call(
         fpo.account_id(),
         "create_pair",
-        &json!(["ETH/USD".to_string(), 8, U128(2000)])
+        &json!(["ETH/USD".to_string(), 8, U128(2000), null, null])
             .to_string()
             .into_bytes(),
         DEFAULT_GAS,
@@ -283,7 +283,7 @@ fn simulate_agg_avg() {
     bob.call(
         fpo.account_id(),
         "create_pair",
-        &json!(["ETH/USD".to_string(), 8, U128(2000)])
+        &json!(["ETH/USD".to_string(), 8, U128(2000), null, null])
             .to_string()
             .into_bytes(),
         DEFAULT_GAS,
@@ -295,7 +295,7 @@ fn simulate_agg_avg() {
     alice.call(
         fpo.account_id(),
         "create_pair",
-        &json!(["ETH/USD".to_string(), 8, U128(3000)])
+        &json!(["ETH/USD".to_string(), 8, U128(3000), null, null])
             .to_string()
             .into_bytes(),
         DEFAULT_GAS,
@@ -307,7 +307,7 @@ fn simulate_agg_avg() {
     carol.call(
         fpo.account_id(),
         "create_pair",
-        &json!(["ETH/USD".to_string(), 8, U128(3000)])
+        &json!(["ETH/USD".to_string(), 8, U128(3000), null, null])
             .to_string()
             .into_bytes(),
         DEFAULT_GAS,
@@ -331,7 +331,8 @@ fn simulate_agg_avg() {
                 alice.account_id(),
                 carol.account_id()
             ],
-            0
+            0,
+            None
         )
     );
 
@@ -349,7 +350,7 @@ fn simulate_agg_median() {
     root.call(
         fpo.account_id(),
         "create_pair",
-        &json!(["ETH/USD".to_string(), 8, U128(2000)])
+        &json!(["ETH/USD".to_string(), 8, U128(2000), null, null])
             .to_string()
             .into_bytes(),
         DEFAULT_GAS,
@@ -361,7 +362,7 @@ fn simulate_agg_median() {
     bob.call(
         fpo.account_id(),
         "create_pair",
-        &json!(["ETH/USD".to_string(), 8, U128(4000)])
+        &json!(["ETH/USD".to_string(), 8, U128(4000), null, null])
             .to_string()
             .into_bytes(),
         DEFAULT_GAS,
@@ -373,7 +374,7 @@ fn simulate_agg_median() {
     alice.call(
         fpo.account_id(),
         "create_pair",
-        &json!(["ETH/USD".to_string(), 8, U128(4000)])
+        &json!(["ETH/USD".to_string(), 8, U128(4000), null, null])
             .to_string()
             .into_bytes(),
         DEFAULT_GAS,
@@ -385,7 +386,7 @@ fn simulate_agg_median() {
     carol.call(
         fpo.account_id(),
         "create_pair",
-        &json!(["ETH/USD".to_string(), 8, U128(2000)])
+        &json!(["ETH/USD".to_string(), 8, U128(2000), null, null])
             .to_string()
             .into_bytes(),
         DEFAULT_GAS,
@@ -409,7 +410,8 @@ fn simulate_agg_median() {
                 alice.account_id(),
                 carol.account_id()
             ],
-            0
+            0,
+            None
         )
     );
 
@@ -427,7 +429,7 @@ fn simulate_agg_median_diff_ids() {
     root.call(
         fpo.account_id(),
         "create_pair",
-        &json!(["ETH-USD".to_string(), 8, U128(2000)])
+        &json!(["ETH-USD".to_string(), 8, U128(2000), null, null])
             .to_string()
             .into_bytes(),
         DEFAULT_GAS,
@@ -439,7 +441,7 @@ fn simulate_agg_median_diff_ids() {
     bob.call(
         fpo.account_id(),
         "create_pair",
-        &json!(["ETH / USD".to_string(), 8, U128(4000)])
+        &json!(["ETH / USD".to_string(), 8, U128(4000), null, null])
             .to_string()
             .into_bytes(),
         DEFAULT_GAS,
@@ -451,7 +453,7 @@ fn simulate_agg_median_diff_ids() {
     alice.call(
         fpo.account_id(),
         "create_pair",
-        &json!(["ETH/USD".to_string(), 8, U128(4000)])
+        &json!(["ETH/USD".to_string(), 8, U128(4000), null, null])
             .to_string()
             .into_bytes(),
         DEFAULT_GAS,
@@ -463,7 +465,7 @@ fn simulate_agg_median_diff_ids() {
     carol.call(
         fpo.account_id(),
         "create_pair",
-        &json!(["ETH/USD".to_string(), 8, U128(2000)])
+        &json!(["ETH/USD".to_string(), 8, U128(2000), null, null])
             .to_string()
             .into_bytes(),
         DEFAULT_GAS,
@@ -487,7 +489,8 @@ fn simulate_agg_median_diff_ids() {
                 alice.account_id(),
                 carol.account_id()
             ],
-            0
+            0,
+            None
         )
     );
 
@@ -495,3 +498,106 @@ fn simulate_agg_median_diff_ids() {
     println!("Returned MEDIAN: {:?}", &median.unwrap_json_value());
     debug_assert_eq!(&median.unwrap_json_value(), &"3000".to_string());
 }
+
+#[test]
+fn simulate_bond_and_unbond() {
+    let (root, fpo) = init();
+    call!(root, fpo.new()).assert_success();
+    // each simulated block advances the clock by a second
+    call!(root, fpo.set_unbonding_delay(5_000_000_000)).assert_success();
+
+    // bond from bob
+    let bob = root.create_user("bob".parse().unwrap(), to_yocto("1000000"));
+    call!(bob, fpo.bond(), deposit = to_yocto("100")).assert_success();
+    let bond = call!(root, fpo.get_bond(bob.account_id()));
+    debug_assert_eq!(
+        &bond.unwrap_json_value()["bond"].to_owned(),
+        &to_yocto("100").to_string()
+    );
+
+    // withdrawing fails before the unbonding delay passed
+    call!(bob, fpo.unbond(U128(to_yocto("40")))).assert_success();
+    let err = call!(bob, fpo.withdraw_unbonded()).promise_errors();
+    println!("ERROR: {:?}", err);
+    assert!(!err.is_empty());
+
+    // and succeeds afterwards
+    root.borrow_runtime_mut().produce_blocks(10).unwrap();
+    let balance_before = bob.account().unwrap().amount;
+    call!(bob, fpo.withdraw_unbonded()).assert_success();
+    assert!(bob.account().unwrap().amount > balance_before);
+
+    let bond = call!(root, fpo.get_bond(bob.account_id()));
+    debug_assert_eq!(
+        &bond.unwrap_json_value()["bond"].to_owned(),
+        &to_yocto("60").to_string()
+    );
+    debug_assert!(bond.unwrap_json_value()["unbonding"].is_null());
+}
+
+#[test]
+fn simulate_slash() {
+    let (root, fpo) = init();
+    call!(root, fpo.new()).assert_success();
+
+    let bob = root.create_user("bob".parse().unwrap(), to_yocto("1000000"));
+    call!(bob, fpo.bond(), deposit = to_yocto("100")).assert_success();
+
+    // only admins can slash
+    let err = call!(bob, fpo.slash(bob.account_id(), U128(to_yocto("30")))).promise_errors();
+    assert!(!err.is_empty());
+
+    call!(root, fpo.slash(bob.account_id(), U128(to_yocto("30")))).assert_success();
+    let bond = call!(root, fpo.get_bond(bob.account_id()));
+    debug_assert_eq!(
+        &bond.unwrap_json_value()["bond"].to_owned(),
+        &to_yocto("70").to_string()
+    );
+    let slashed = call!(root, fpo.get_slashed_funds());
+    debug_assert_eq!(&slashed.unwrap_json_value(), &to_yocto("30").to_string());
+}
+
+#[test]
+fn simulate_aggregate_federated() {
+    let (root, fpo) = init();
+    let backup: ContractAccount<FPOContractContract> = deploy!(
+        contract: FPOContractContract,
+        contract_id: "backupfpo".to_string(),
+        bytes: &FPO_BYTES,
+        signer_account: root
+    );
+    call!(root, fpo.new()).assert_success();
+    call!(root, backup.new()).assert_success();
+
+    // both deployments get a different price for the same pair
+    for (oracle, price) in [(fpo.account_id(), 2000), (backup.account_id(), 3000)] {
+        root.call(
+            oracle,
+            "create_pair",
+            &json!(["ETH/USD".to_string(), 8, U128(price), null, null])
+                .to_string()
+                .into_bytes(),
+            DEFAULT_GAS,
+            STORAGE_COST, // attached deposit
+        )
+        .assert_success();
+    }
+
+    let outcome = root.call(
+        fpo.account_id(),
+        "aggregate_federated",
+        &json!({
+            "oracles": [fpo.account_id(), backup.account_id()],
+            "pairs": ["ETH/USD"],
+            "providers": [root.account_id()],
+            "min_last_update": 0,
+            "method": "Mean"
+        })
+        .to_string()
+        .into_bytes(),
+        DEFAULT_GAS,
+        0,
+    );
+    outcome.assert_success();
+    debug_assert_eq!(&outcome.unwrap_json_value(), &"2500".to_string());
+}